@@ -1,7 +1,7 @@
-use kiteticker_async::{KiteTickerAsync, Mode, TickerMessage};
+use kiteticker_async::{KiteTickerAsync, KiteTickerError, Mode, TickerMessage};
 
 #[tokio::main]
-pub async fn main() -> Result<(), String> {
+pub async fn main() -> Result<(), KiteTickerError> {
   let api_key = std::env::var("KITE_API_KEY").unwrap_or_default();
   let access_token = std::env::var("KITE_ACCESS_TOKEN").unwrap_or_default();
   let ticker = KiteTickerAsync::connect(&api_key, &access_token).await?;
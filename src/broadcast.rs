@@ -0,0 +1,206 @@
+use std::{
+  collections::VecDeque,
+  pin::Pin,
+  sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc, Mutex,
+  },
+  task::{Context, Poll},
+};
+
+use futures_util::{Stream, StreamExt};
+use tokio::sync::mpsc;
+
+use crate::{
+  KiteTickerError, KiteTickerHandle, KiteTickerSubscriber, TickerMessage,
+};
+
+type Message = Result<TickerMessage, KiteTickerError>;
+
+#[derive(Debug, Default)]
+struct Lag {
+  /// Gaps not yet reported, as the number of messages sent before each gap
+  /// and the number of messages dropped there
+  gaps: Mutex<VecDeque<(u64, u64)>>,
+  /// Messages dropped over the lifetime of the receiver
+  total: AtomicU64,
+}
+
+impl Lag {
+  /// Record a message dropped after `sent` messages were sent
+  fn skip(&self, sent: u64) {
+    let mut gaps = self.gaps.lock().unwrap();
+    match gaps.back_mut() {
+      Some((at, skipped)) if *at == sent => *skipped += 1,
+      _ => gaps.push_back((sent, 1)),
+    }
+    self.total.fetch_add(1, Ordering::Relaxed);
+  }
+}
+
+#[derive(Debug)]
+struct Slot {
+  sender: mpsc::Sender<Message>,
+  lag: Arc<Lag>,
+  /// Messages sent to the receiver so far
+  sent: u64,
+}
+
+#[derive(Debug, Default)]
+struct Slots {
+  slots: Vec<Slot>,
+  /// The subscriber ended, new receivers end right away
+  closed: bool,
+}
+
+#[derive(Debug)]
+///
+/// Fans the messages of one subscriber out to any number of receivers
+///
+/// Every receiver has its own bounded buffer. Messages that do not fit in a
+/// full buffer are dropped for that receiver only and reported to it as
+/// [`KiteTickerError::Lagged`], so a slow receiver never holds up the
+/// connection or the other receivers.
+///
+/// The connection stays open as long as the broadcast or any of its
+/// receivers is alive.
+///
+pub struct KiteTickerBroadcast {
+  handle: KiteTickerHandle,
+  slots: Arc<Mutex<Slots>>,
+  capacity: usize,
+}
+
+impl KiteTickerBroadcast {
+  /// Start fanning out the subscriber's messages, each receiver buffering up
+  /// to `capacity` messages
+  pub fn new(subscriber: KiteTickerSubscriber, capacity: usize) -> Self {
+    let handle = subscriber.handle();
+    let slots = Arc::new(Mutex::new(Slots::default()));
+    tokio::spawn(pump(subscriber, slots.clone()));
+    Self {
+      handle,
+      slots,
+      capacity: capacity.max(1),
+    }
+  }
+
+  /// Cloneable handle to control the connection from other tasks
+  pub fn handle(&self) -> KiteTickerHandle {
+    self.handle.clone()
+  }
+
+  /// New receiver getting every message from now on
+  pub fn receiver(&self) -> BroadcastReceiver {
+    self.receiver_with_capacity(self.capacity)
+  }
+
+  /// New receiver with its own buffer size
+  pub fn receiver_with_capacity(&self, capacity: usize) -> BroadcastReceiver {
+    let (sender, messages) = mpsc::channel(capacity.max(1));
+    let lag = Arc::new(Lag::default());
+    let mut slots = self.slots.lock().unwrap();
+    if !slots.closed {
+      slots.slots.push(Slot {
+        sender,
+        lag: lag.clone(),
+        sent: 0,
+      });
+    }
+    BroadcastReceiver {
+      messages,
+      lag,
+      received: 0,
+    }
+  }
+
+  /// Number of receivers still alive
+  pub fn receiver_count(&self) -> usize {
+    let mut slots = self.slots.lock().unwrap();
+    slots.slots.retain(|s| !s.sender.is_closed());
+    slots.slots.len()
+  }
+}
+
+async fn pump(mut subscriber: KiteTickerSubscriber, slots: Arc<Mutex<Slots>>) {
+  while let Some(message) = subscriber.next().await {
+    let mut slots_guard = slots.lock().unwrap();
+    slots_guard.slots.retain_mut(|slot| {
+      match slot.sender.try_send(message.clone()) {
+        Ok(_) => {
+          slot.sent += 1;
+          true
+        }
+        Err(mpsc::error::TrySendError::Full(_)) => {
+          slot.lag.skip(slot.sent);
+          true
+        }
+        Err(mpsc::error::TrySendError::Closed(_)) => false,
+      }
+    });
+    // nobody is left to receive, nor to hand out new receivers
+    if slots_guard.slots.is_empty() && Arc::strong_count(&slots) == 1 {
+      break;
+    }
+  }
+
+  let mut slots = slots.lock().unwrap();
+  slots.closed = true;
+  slots.slots.clear();
+}
+
+#[derive(Debug)]
+///
+/// One of the receivers of a [`KiteTickerBroadcast`]
+///
+pub struct BroadcastReceiver {
+  messages: mpsc::Receiver<Message>,
+  lag: Arc<Lag>,
+  /// Messages received so far
+  received: u64,
+}
+
+impl BroadcastReceiver {
+  /// Get the next message, waiting if necessary. If the result is None then
+  /// the connection is terminated
+  ///
+  /// Messages dropped because the buffer was full are reported as
+  /// [`KiteTickerError::Lagged`] where they would have been, after the
+  /// messages that were already buffered.
+  pub async fn recv(&mut self) -> Option<Message> {
+    futures_util::future::poll_fn(|cx| self.poll_recv(cx)).await
+  }
+
+  /// Messages dropped over the lifetime of the receiver
+  pub fn dropped(&self) -> u64 {
+    self.lag.total.load(Ordering::Relaxed)
+  }
+
+  fn poll_recv(&mut self, cx: &mut Context<'_>) -> Poll<Option<Message>> {
+    {
+      let mut gaps = self.lag.gaps.lock().unwrap();
+      if let Some(&(at, skipped)) = gaps.front() {
+        if at == self.received {
+          gaps.pop_front();
+          return Poll::Ready(Some(Err(KiteTickerError::Lagged(skipped))));
+        }
+      }
+    }
+    let message = self.messages.poll_recv(cx);
+    if let Poll::Ready(Some(_)) = message {
+      self.received += 1;
+    }
+    message
+  }
+}
+
+impl Stream for BroadcastReceiver {
+  type Item = Message;
+
+  fn poll_next(
+    mut self: Pin<&mut Self>,
+    cx: &mut Context<'_>,
+  ) -> Poll<Option<Self::Item>> {
+    self.poll_recv(cx)
+  }
+}
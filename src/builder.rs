@@ -0,0 +1,446 @@
+use std::{sync::Arc, time::Duration};
+
+use tokio_tungstenite::{
+  client_async_tls_with_config, connect_async_tls_with_config,
+  tungstenite::{
+    client::IntoClientRequest,
+    handshake::client::Request,
+    http::{HeaderName, HeaderValue},
+    protocol::WebSocketConfig,
+  },
+};
+
+use crate::{
+  ticker::{ReconnectPolicy, WsStream},
+  tls::TlsSettings,
+  trace, Certificate, Connector, Credentials, CredentialsProvider,
+  KiteTickerAsync, KiteTickerError, Metrics, Mode, Proxy,
+};
+
+/// Default Kite Connect streaming endpoint
+pub const DEFAULT_ENDPOINT: &str = "wss://ws.kite.trade";
+
+#[derive(Debug, Clone)]
+///
+/// Connection settings shared by a ticker and every reconnect it makes
+///
+pub(crate) struct TickerConfig {
+  pub(crate) endpoint: String,
+  pub(crate) credentials: Option<Credentials>,
+  pub(crate) credentials_provider: Option<Arc<dyn CredentialsProvider>>,
+  pub(crate) connect_timeout: Option<Duration>,
+  pub(crate) headers: Vec<(String, String)>,
+  pub(crate) websocket_config: Option<WebSocketConfig>,
+  pub(crate) default_mode: Mode,
+  pub(crate) reconnect_policy: ReconnectPolicy,
+  pub(crate) message_buffer: usize,
+  pub(crate) read_timeout: Option<Duration>,
+  pub(crate) ping_timeout: Duration,
+  pub(crate) proxy: Option<Proxy>,
+  pub(crate) tls: TlsSettings,
+  pub(crate) metrics: Metrics,
+  pub(crate) raw_frames: bool,
+}
+
+impl TickerConfig {
+  /// Credentials for the next connection, asking the provider if there is
+  /// one
+  pub(crate) async fn fetch_credentials(
+    &self,
+  ) -> Result<Credentials, KiteTickerError> {
+    match (&self.credentials_provider, &self.credentials) {
+      (Some(provider), _) => provider.credentials().await,
+      (None, Some(credentials)) => Ok(credentials.clone()),
+      (None, None) => Err(KiteTickerError::Config(
+        "missing api key or access token".to_string(),
+      )),
+    }
+  }
+
+  /// Handshake request with credentials in the query and the extra headers
+  pub(crate) fn request(
+    &self,
+    credentials: &Credentials,
+  ) -> Result<Request, KiteTickerError> {
+    let mut url = url::Url::parse(&self.endpoint).map_err(|e| {
+      KiteTickerError::Config(format!(
+        "invalid endpoint {}: {}",
+        self.endpoint, e
+      ))
+    })?;
+    url
+      .query_pairs_mut()
+      .append_pair("api_key", &credentials.api_key)
+      .append_pair("access_token", &credentials.access_token);
+
+    let mut request = url.as_str().into_client_request()?;
+    for (name, value) in &self.headers {
+      let name = HeaderName::from_bytes(name.as_bytes()).map_err(|e| {
+        KiteTickerError::Config(format!("invalid header name {}: {}", name, e))
+      })?;
+      let value = HeaderValue::from_str(value).map_err(|e| {
+        KiteTickerError::Config(format!(
+          "invalid value for header {}: {}",
+          name, e
+        ))
+      })?;
+      request.headers_mut().insert(name, value);
+    }
+    Ok(request)
+  }
+
+  /// Open a WebSocket connection, through the proxy if there is one,
+  /// honouring the connect timeout
+  pub(crate) async fn open(
+    &self,
+    credentials: &Credentials,
+  ) -> Result<WsStream, KiteTickerError> {
+    trace::instrument!(
+      self.handshake(credentials),
+      "connect",
+      endpoint = %self.endpoint,
+      proxy = self.proxy.is_some()
+    )
+    .await
+  }
+
+  async fn handshake(
+    &self,
+    credentials: &Credentials,
+  ) -> Result<WsStream, KiteTickerError> {
+    let request = self.request(credentials)?;
+    trace::event!(debug, ?credentials, "opening connection");
+    let connect = async {
+      let (ws_stream, _) = match &self.proxy {
+        Some(proxy) => {
+          let uri = request.uri();
+          let host = uri.host().unwrap_or_default().to_string();
+          let port = uri.port_u16().unwrap_or(match uri.scheme_str() {
+            Some("wss") => 443,
+            _ => 80,
+          });
+          let stream = proxy.tunnel(&host, port).await?;
+          client_async_tls_with_config(
+            request,
+            stream,
+            self.websocket_config,
+            self.tls.connector.clone(),
+          )
+          .await?
+        }
+        None => {
+          connect_async_tls_with_config(
+            request,
+            self.websocket_config,
+            false,
+            self.tls.connector.clone(),
+          )
+          .await?
+        }
+      };
+      Ok(ws_stream)
+    };
+    let opened = match self.connect_timeout {
+      Some(timeout) => tokio::time::timeout(timeout, connect)
+        .await
+        .unwrap_or(Err(KiteTickerError::Timeout(timeout))),
+      None => connect.await,
+    };
+    #[cfg(feature = "tracing")]
+    match &opened {
+      Ok(_) => tracing::info!("connected"),
+      Err(e) => tracing::warn!(error = %e, "connect failed"),
+    }
+    opened
+  }
+}
+
+#[derive(Debug, Clone)]
+///
+/// Builder to configure and connect a [`KiteTickerAsync`]
+///
+/// ```no_run
+/// use std::time::Duration;
+/// use kiteticker_async::{KiteTickerAsync, Mode};
+///
+/// # async fn run() -> Result<(), kiteticker_async::KiteTickerError> {
+/// let ticker = KiteTickerAsync::builder()
+///   .endpoint("wss://ws.kite.trade")
+///   .credentials("api_key", "access_token")
+///   .connect_timeout(Duration::from_secs(10))
+///   .header("X-Kite-Version", "3")
+///   .default_mode(Mode::Full)
+///   .connect()
+///   .await?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct KiteTickerAsyncBuilder {
+  endpoint: String,
+  credentials: Option<Credentials>,
+  credentials_provider: Option<Arc<dyn CredentialsProvider>>,
+  connect_timeout: Option<Duration>,
+  headers: Vec<(String, String)>,
+  websocket_config: Option<WebSocketConfig>,
+  default_mode: Mode,
+  reconnect_policy: ReconnectPolicy,
+  message_buffer: usize,
+  read_timeout: Option<Duration>,
+  ping_timeout: Duration,
+  proxy: Option<Proxy>,
+  proxy_from_env: bool,
+  tls: TlsSettings,
+  metrics: Option<Metrics>,
+  raw_frames: bool,
+}
+
+impl Default for KiteTickerAsyncBuilder {
+  fn default() -> Self {
+    Self {
+      endpoint: DEFAULT_ENDPOINT.to_string(),
+      credentials: None,
+      credentials_provider: None,
+      connect_timeout: None,
+      headers: vec![],
+      websocket_config: None,
+      default_mode: Mode::default(),
+      reconnect_policy: ReconnectPolicy::default(),
+      message_buffer: 1024,
+      read_timeout: None,
+      ping_timeout: Duration::from_secs(10),
+      proxy: None,
+      proxy_from_env: false,
+      tls: TlsSettings::default(),
+      metrics: None,
+      raw_frames: false,
+    }
+  }
+}
+
+impl KiteTickerAsyncBuilder {
+  /// WebSocket endpoint to connect to, defaults to [`DEFAULT_ENDPOINT`]
+  pub fn endpoint(mut self, endpoint: impl Into<String>) -> Self {
+    self.endpoint = endpoint.into();
+    self
+  }
+
+  /// API key and access token used to authenticate the connection
+  pub fn credentials(
+    mut self,
+    api_key: impl Into<String>,
+    access_token: impl Into<String>,
+  ) -> Self {
+    self.credentials = Some(Credentials::new(api_key, access_token));
+    self
+  }
+
+  /// Ask the provider for credentials before every connection attempt,
+  /// including reconnects, instead of using fixed credentials
+  pub fn credentials_provider(
+    mut self,
+    provider: impl CredentialsProvider + 'static,
+  ) -> Self {
+    self.credentials_provider = Some(Arc::new(provider));
+    self
+  }
+
+  /// Maximum time allowed for the TCP, TLS and WebSocket handshakes
+  pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+    self.connect_timeout = Some(timeout);
+    self
+  }
+
+  /// Extra header sent with the WebSocket handshake
+  pub fn header(
+    mut self,
+    name: impl Into<String>,
+    value: impl Into<String>,
+  ) -> Self {
+    self.headers.push((name.into(), value.into()));
+    self
+  }
+
+  /// User agent sent with the WebSocket handshake
+  pub fn user_agent(self, user_agent: impl Into<String>) -> Self {
+    self.header("User-Agent", user_agent)
+  }
+
+  /// Low level WebSocket settings such as maximum message and frame size
+  pub fn websocket_config(mut self, config: WebSocketConfig) -> Self {
+    self.websocket_config = Some(config);
+    self
+  }
+
+  /// Mode used when subscribing without an explicit mode
+  pub fn default_mode(mut self, mode: Mode) -> Self {
+    self.default_mode = mode;
+    self
+  }
+
+  /// Policy used to reconnect when the connection drops
+  pub fn reconnect_policy(mut self, policy: ReconnectPolicy) -> Self {
+    self.reconnect_policy = policy;
+    self
+  }
+
+  /// Number of inbound messages buffered for the consumer before the
+  /// connection stops reading from the socket
+  pub fn message_buffer(mut self, capacity: usize) -> Self {
+    self.message_buffer = capacity.max(1);
+    self
+  }
+
+  /// Treat the connection as dead when nothing, heartbeats included, is
+  /// received for this long
+  ///
+  /// Kite sends a heartbeat every second, so a few seconds is a reasonable
+  /// value. A dead connection is reported as [`crate::TickerMessage::Stale`]
+  /// and reconnected if the reconnect policy allows it.
+  pub fn read_timeout(mut self, timeout: Duration) -> Self {
+    self.read_timeout = Some(timeout);
+    self
+  }
+
+  /// Fail a ping with [`KiteTickerError::Timeout`] when its pong does not
+  /// arrive within this long, defaults to 10 seconds
+  pub fn ping_timeout(mut self, timeout: Duration) -> Self {
+    self.ping_timeout = timeout;
+    self
+  }
+
+  /// Tunnel the connection through a proxy
+  pub fn proxy(mut self, proxy: Proxy) -> Self {
+    self.proxy = Some(proxy);
+    self
+  }
+
+  /// Tunnel the connection through the proxy set in the environment, see
+  /// [`Proxy::from_env`], unless a proxy is set explicitly
+  pub fn proxy_from_env(mut self) -> Self {
+    self.proxy_from_env = true;
+    self
+  }
+
+  /// Trust an extra root certificate, such as a corporate CA or the
+  /// certificate of a local test server
+  pub fn add_root_certificate(mut self, certificate: Certificate) -> Self {
+    self.tls.roots.push(certificate);
+    self
+  }
+
+  /// Whether to trust the root certificates of the TLS backend, the system
+  /// store for `native-tls` and `rustls-tls-native-roots` or the Mozilla
+  /// roots for `rustls-tls-webpki-roots`. Defaults to true
+  pub fn tls_built_in_root_certs(mut self, enabled: bool) -> Self {
+    self.tls.built_in_roots = enabled;
+    self
+  }
+
+  /// Connector used for the TLS handshake, overriding the root certificate
+  /// settings
+  pub fn tls_connector(mut self, connector: Connector) -> Self {
+    self.tls.connector = Some(connector);
+    self
+  }
+
+  /// Record into the given registry instead of one of the ticker's own, so
+  /// that several tickers can be aggregated
+  pub fn metrics(mut self, metrics: Metrics) -> Self {
+    self.metrics = Some(metrics);
+    self
+  }
+
+  /// Deliver binary frames as [`crate::TickerMessage::Frame`] instead of
+  /// decoding them into ticks, so that they can be read field by field
+  /// with a [`crate::FrameView`] without allocating per packet
+  ///
+  /// Heartbeats are still delivered as [`crate::TickerMessage::Heartbeat`],
+  /// and damaged packets are left for the consumer to find.
+  pub fn raw_frames(mut self, enabled: bool) -> Self {
+    self.raw_frames = enabled;
+    self
+  }
+
+  /// Establish a connection with the configured server
+  pub async fn connect(self) -> Result<KiteTickerAsync, KiteTickerError> {
+    let config = self.build()?;
+    let credentials = config.fetch_credentials().await?;
+    let ws_stream = config.open(&credentials).await?;
+    Ok(KiteTickerAsync::new(config, credentials, ws_stream))
+  }
+
+  fn build(self) -> Result<TickerConfig, KiteTickerError> {
+    if self.credentials.is_none() && self.credentials_provider.is_none() {
+      return Err(KiteTickerError::Config(
+        "missing api key or access token".to_string(),
+      ));
+    }
+    let proxy = match self.proxy {
+      Some(proxy) => Some(proxy),
+      None if self.proxy_from_env => {
+        let host = url::Url::parse(&self.endpoint)
+          .ok()
+          .and_then(|url| url.host_str().map(str::to_string))
+          .unwrap_or_default();
+        Proxy::from_env(&host)?
+      }
+      None => None,
+    };
+    Ok(TickerConfig {
+      endpoint: self.endpoint,
+      credentials: self.credentials,
+      credentials_provider: self.credentials_provider,
+      connect_timeout: self.connect_timeout,
+      headers: self.headers,
+      websocket_config: self.websocket_config,
+      default_mode: self.default_mode,
+      reconnect_policy: self.reconnect_policy,
+      message_buffer: self.message_buffer,
+      read_timeout: self.read_timeout,
+      ping_timeout: self.ping_timeout,
+      proxy,
+      tls: self.tls.resolve()?,
+      metrics: self.metrics.unwrap_or_default(),
+      raw_frames: self.raw_frames,
+    })
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::KiteTickerAsyncBuilder;
+  use crate::Credentials;
+
+  #[test]
+  fn test_request() {
+    let config = KiteTickerAsyncBuilder::default()
+      .endpoint("ws://localhost:8080/ticker")
+      .credentials("key", "tok&en=/+")
+      .user_agent("kiteticker-async")
+      .build()
+      .unwrap();
+    let credentials = Credentials::new("key", "tok&en=/+");
+    let request = config.request(&credentials).unwrap();
+    assert_eq!(
+      request.uri().to_string(),
+      "ws://localhost:8080/ticker?api_key=key&access_token=tok%26en%3D%2F%2B"
+    );
+    assert_eq!(request.headers()["User-Agent"], "kiteticker-async");
+
+    let config = KiteTickerAsyncBuilder::default()
+      .endpoint("not a url")
+      .credentials("key", "token")
+      .build()
+      .unwrap();
+    assert!(config.request(&credentials).is_err());
+
+    let config = KiteTickerAsyncBuilder::default()
+      .credentials("key", "token")
+      .header("bad header", "value")
+      .build()
+      .unwrap();
+    assert!(config.request(&credentials).is_err());
+
+    assert!(KiteTickerAsyncBuilder::default().build().is_err());
+  }
+}
@@ -0,0 +1,134 @@
+use std::{
+  collections::HashMap,
+  sync::{Arc, Mutex},
+  time::Duration,
+};
+
+use futures_util::{Stream, StreamExt};
+use tokio::{
+  sync::Notify,
+  time::{Interval, MissedTickBehavior},
+};
+
+use crate::{KiteTickerError, KiteTickerSubscriber, Tick, TickerMessage};
+
+#[derive(Debug, Default)]
+struct Latest {
+  /// Newest tick of every token updated since the last snapshot
+  ticks: HashMap<u32, Tick>,
+  /// Ticks overwritten before they were handed over, per token
+  dropped: HashMap<u32, u64>,
+  /// The feed ended
+  closed: bool,
+}
+
+#[derive(Debug, Default)]
+struct Shared {
+  latest: Mutex<Latest>,
+  updated: Notify,
+}
+
+#[derive(Debug)]
+///
+/// Keeps only the newest tick of every instrument for consumers slower than
+/// the feed
+///
+/// Each snapshot holds the latest tick of every instrument updated since the
+/// previous snapshot, older ticks of the same instrument are dropped and
+/// counted. Messages other than ticks are discarded.
+///
+pub struct KiteTickerConflator {
+  shared: Arc<Shared>,
+  interval: Option<Interval>,
+}
+
+impl KiteTickerConflator {
+  /// Conflate the ticks of a subscriber
+  pub fn new(subscriber: KiteTickerSubscriber) -> Self {
+    Self::from_stream(subscriber)
+  }
+
+  /// Conflate the ticks of any stream of messages, such as a
+  /// [`crate::BroadcastReceiver`]
+  pub fn from_stream<S>(messages: S) -> Self
+  where
+    S: Stream<Item = Result<TickerMessage, KiteTickerError>>
+      + Send
+      + Unpin
+      + 'static,
+  {
+    let shared = Arc::new(Shared::default());
+    tokio::spawn(collect(messages, shared.clone()));
+    Self {
+      shared,
+      interval: None,
+    }
+  }
+
+  /// Hand over snapshots at most once per `period` instead of as soon as
+  /// a tick arrives
+  pub fn sample_interval(mut self, period: Duration) -> Self {
+    let mut interval = tokio::time::interval(period);
+    interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    self.interval = Some(interval);
+    self
+  }
+
+  /// Get the latest tick of every instrument updated since the previous
+  /// snapshot, waiting if nothing was. If the result is None then the
+  /// connection is terminated
+  pub async fn next_snapshot(&mut self) -> Option<HashMap<u32, Tick>> {
+    loop {
+      let updated = self.shared.updated.notified();
+      if let Some(interval) = self.interval.as_mut() {
+        interval.tick().await;
+      }
+      {
+        let mut latest = self.shared.latest.lock().unwrap();
+        if !latest.ticks.is_empty() {
+          return Some(std::mem::take(&mut latest.ticks));
+        }
+        if latest.closed {
+          return None;
+        }
+      }
+      if self.interval.is_none() {
+        updated.await;
+      }
+    }
+  }
+
+  /// Ticks dropped so far because a newer one of the same instrument
+  /// arrived first, per instrument
+  pub fn dropped(&self) -> HashMap<u32, u64> {
+    self.shared.latest.lock().unwrap().dropped.clone()
+  }
+}
+
+async fn collect<S>(mut messages: S, shared: Arc<Shared>)
+where
+  S: Stream<Item = Result<TickerMessage, KiteTickerError>> + Unpin,
+{
+  while let Some(message) = messages.next().await {
+    // the conflator is gone
+    if Arc::strong_count(&shared) == 1 {
+      return;
+    }
+    let Ok(TickerMessage::Ticks(ticks)) = message else {
+      continue;
+    };
+    {
+      let mut latest = shared.latest.lock().unwrap();
+      for tick in ticks {
+        let token = tick.instrument_token;
+        if latest.ticks.insert(token, tick.content).is_some() {
+          *latest.dropped.entry(token).or_default() += 1;
+        }
+      }
+    }
+    shared.updated.notify_waiters();
+  }
+
+  shared.latest.lock().unwrap().closed = true;
+  shared.updated.notify_waiters();
+}
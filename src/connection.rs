@@ -0,0 +1,817 @@
+use std::{
+  collections::{HashMap, VecDeque},
+  sync::{Arc, Mutex, RwLock},
+  time::Duration,
+};
+
+use chrono::{DateTime, FixedOffset, Utc};
+use futures_util::{stream::iter, SinkExt, StreamExt};
+use serde_json::json;
+use tokio::{
+  sync::{mpsc, oneshot},
+  time::Instant,
+};
+use tokio_tungstenite::tungstenite::{
+  protocol::{frame::coding::CloseCode, CloseFrame},
+  Message,
+};
+
+use crate::{
+  builder::TickerConfig,
+  metrics::Metrics,
+  models::{ist, Request, TextMessage, TickMessage},
+  ticker::{ConnectionState, WsStream, CLOSE_TIMEOUT},
+  trace, Credentials, DecodeError, DisconnectReason, FrameView,
+  KiteTickerError, Mode, ShutdownOptions, ShutdownSummary, TickerMessage,
+};
+
+pub(crate) type Reply = oneshot::Sender<Result<(), KiteTickerError>>;
+
+#[derive(Debug, Default)]
+///
+/// State owned by the connection task and readable from every handle
+///
+pub(crate) struct Shared {
+  pub(crate) subscriptions: RwLock<HashMap<u32, Mode>>,
+  pub(crate) last_rtt: Mutex<Option<Duration>>,
+  pub(crate) state: Mutex<ConnectionState>,
+}
+
+#[derive(Debug)]
+///
+/// Instructions sent by handles to the task owning the socket
+///
+pub(crate) enum Command {
+  Subscribe {
+    tokens: Vec<u32>,
+    mode: Mode,
+    reply: Reply,
+  },
+  SetMode {
+    tokens: Vec<u32>,
+    mode: Mode,
+    reply: Reply,
+  },
+  Unsubscribe {
+    tokens: Vec<u32>,
+    reply: Reply,
+  },
+  Close {
+    reply: Reply,
+  },
+  Ping {
+    reply: oneshot::Sender<Result<Duration, KiteTickerError>>,
+  },
+  Shutdown {
+    options: ShutdownOptions,
+    reply: oneshot::Sender<ShutdownSummary>,
+  },
+  UpdateCredentials {
+    credentials: Credentials,
+    reply: Reply,
+  },
+}
+
+/// What woke up the task serving a live connection
+enum Event {
+  Command(Option<Command>),
+  Inbound(Option<Result<Message, tokio_tungstenite::tungstenite::Error>>),
+  /// The oldest unanswered ping timed out
+  PingTimeout,
+}
+
+/// Why a connection stopped being served
+enum Disconnect {
+  /// Closed on request, or nobody is left to talk to
+  Closed,
+  /// Lost without being asked to
+  Dropped(DisconnectReason),
+}
+
+///
+/// Owns the WebSocket, executes commands and delivers inbound messages
+///
+pub(crate) struct Worker {
+  config: Arc<TickerConfig>,
+  /// Credentials of the current, or last, connection
+  credentials: Credentials,
+  /// Credentials updated while disconnected, used for the next attempt
+  /// even if there is a provider
+  updated_credentials: Option<Credentials>,
+  commands: mpsc::UnboundedReceiver<Command>,
+  commands_open: bool,
+  messages: mpsc::Sender<Result<TickerMessage, KiteTickerError>>,
+  shared: Arc<Shared>,
+  pings:
+    HashMap<u64, (Instant, oneshot::Sender<Result<Duration, KiteTickerError>>)>,
+  next_ping: u64,
+  pending: VecDeque<Result<TickerMessage, KiteTickerError>>,
+  closing: bool,
+  /// When to stop waiting for the server to acknowledge the close frame
+  close_deadline: Option<Instant>,
+  /// Graceful shutdown to finish once the connection is closed
+  shutdown: Option<(
+    ShutdownOptions,
+    ShutdownSummary,
+    oneshot::Sender<ShutdownSummary>,
+  )>,
+}
+
+impl Worker {
+  pub(crate) fn new(
+    config: Arc<TickerConfig>,
+    credentials: Credentials,
+    commands: mpsc::UnboundedReceiver<Command>,
+    messages: mpsc::Sender<Result<TickerMessage, KiteTickerError>>,
+    shared: Arc<Shared>,
+  ) -> Self {
+    Self {
+      config,
+      credentials,
+      updated_credentials: None,
+      commands,
+      commands_open: true,
+      messages,
+      shared,
+      pings: HashMap::new(),
+      next_ping: 0,
+      pending: VecDeque::new(),
+      closing: false,
+      close_deadline: None,
+      shutdown: None,
+    }
+  }
+
+  pub(crate) async fn run(mut self, ws_stream: WsStream) {
+    let mut ws_stream = Some(ws_stream);
+    let mut attempt = 0;
+    let mut last_error = None;
+    self.connected();
+    loop {
+      if let Some(stream) = ws_stream.take() {
+        self.set_state(ConnectionState::Connected);
+        match self.serve(stream).await {
+          Disconnect::Closed => {
+            trace::event!(debug, "connection closed on request");
+            self.pending.push_back(Ok(TickerMessage::Disconnected {
+              reason: DisconnectReason::Requested,
+            }));
+            break;
+          }
+          Disconnect::Dropped(reason) => {
+            trace::event!(info, ?reason, "connection dropped");
+            let retryable = reason.is_retryable();
+            let error = match &reason {
+              DisconnectReason::Lost(error) => error.clone(),
+              _ => None,
+            };
+            self
+              .pending
+              .push_back(Ok(TickerMessage::Disconnected { reason }));
+            if !self.should_reconnect() || !retryable {
+              if let Some(e) = error {
+                self.pending.push_back(Err(e));
+              }
+              break;
+            }
+            attempt = 1;
+            last_error = None;
+          }
+        }
+        continue;
+      }
+
+      let policy = &self.config.reconnect_policy;
+      if policy.exhausted(attempt) {
+        trace::event!(warn, attempts = attempt - 1, "gave up reconnecting");
+        self.pending.push_back(Ok(TickerMessage::GaveUp {
+          attempts: attempt - 1,
+          last_error,
+        }));
+        break;
+      }
+      let delay = policy.delay_for(attempt);
+      self.set_state(ConnectionState::Reconnecting { attempt });
+      self.config.metrics.reconnect_attempt();
+      trace::event!(info, attempt, ?delay, "reconnecting");
+      self.pending.push_back(Ok(TickerMessage::Reconnecting {
+        attempt,
+        delay,
+        last_error: last_error.clone(),
+      }));
+      if !self.wait(delay).await {
+        break;
+      }
+
+      let reopened = match self.next_credentials().await {
+        Ok(credentials) => self.reopen(credentials).await,
+        Err(e) => Err(e),
+      };
+      match reopened {
+        Ok((stream, tokens)) => {
+          self.config.metrics.reconnect();
+          trace::event!(info, tokens = tokens.len(), "resubscribed");
+          self.connected();
+          self
+            .pending
+            .push_back(Ok(TickerMessage::Resubscribed { tokens }));
+          ws_stream = Some(stream);
+        }
+        // retrying can not get past the error unless a provider has fresh
+        // credentials to offer
+        Err(e)
+          if !e.is_retryable()
+            && self.config.credentials_provider.is_none() =>
+        {
+          trace::event!(warn, error = %e, "gave up reconnecting");
+          self.pending.push_back(Err(e));
+          break;
+        }
+        Err(e) => {
+          trace::event!(info, error = %e, "reconnect attempt failed");
+          last_error = Some(e);
+          attempt += 1;
+        }
+      }
+    }
+    self.set_state(ConnectionState::Closed);
+    match self.shutdown.take() {
+      Some((options, mut summary, reply)) => {
+        (summary.drained, summary.dropped) =
+          self.drain(options.drain_timeout).await;
+        let _ = reply.send(summary);
+      }
+      None => self.flush().await,
+    }
+  }
+
+  fn connected(&mut self) {
+    self.pending.push_back(Ok(TickerMessage::Connected {
+      at: Utc::now(),
+      endpoint: self.config.endpoint.clone(),
+    }));
+  }
+
+  fn set_state(&self, state: ConnectionState) {
+    *self.shared.state.lock().unwrap() = state;
+  }
+
+  fn should_reconnect(&self) -> bool {
+    self.config.reconnect_policy.enabled && !self.closing
+  }
+
+  /// Send a close frame, the connection is served until the server
+  /// acknowledges it or [`CLOSE_TIMEOUT`] passes
+  async fn close(
+    &mut self,
+    ws_stream: &mut WsStream,
+  ) -> Result<(), KiteTickerError> {
+    self.closing = true;
+    let deadline = Instant::now() + CLOSE_TIMEOUT;
+    self.close_deadline = Some(deadline);
+    match tokio::time::timeout_at(deadline, ws_stream.close(None)).await {
+      Ok(closed) => closed.map_err(KiteTickerError::from),
+      Err(_) => Err(KiteTickerError::Timeout(CLOSE_TIMEOUT)),
+    }
+  }
+
+  /// Serve a live connection until it is closed or lost
+  async fn serve(&mut self, mut ws_stream: WsStream) -> Disconnect {
+    let read_timeout = self.config.read_timeout;
+    let mut last_activity = Instant::now();
+    let mut close_frame = None;
+    let disconnect = loop {
+      let idle_deadline = last_activity + read_timeout.unwrap_or_default();
+      let ping_deadline = self.ping_deadline().unwrap_or(idle_deadline);
+      let close_deadline = self.close_deadline;
+      let event = tokio::select! {
+        command = self.commands.recv(), if self.commands_open => {
+          Event::Command(command)
+        }
+        permit = self.messages.reserve(), if !self.pending.is_empty() => {
+          match permit {
+            Ok(permit) => permit.send(self.pending.pop_front().unwrap()),
+            Err(_) => break Disconnect::Closed,
+          }
+          last_activity = Instant::now();
+          continue;
+        }
+        message = ws_stream.next(), if self.pending.is_empty() => {
+          last_activity = Instant::now();
+          Event::Inbound(message)
+        }
+        _ = tokio::time::sleep_until(idle_deadline),
+          if read_timeout.is_some() && self.pending.is_empty() => {
+          if self.closing {
+            break Disconnect::Closed;
+          }
+          let idle = last_activity.elapsed();
+          trace::event!(warn, ?idle, "connection is stale");
+          self.pending.push_back(Ok(TickerMessage::Stale { idle }));
+          break Disconnect::Dropped(DisconnectReason::Stale { idle });
+        }
+        _ = tokio::time::sleep_until(ping_deadline),
+          if !self.pings.is_empty() => {
+          Event::PingTimeout
+        }
+        _ = tokio::time::sleep_until(close_deadline.unwrap_or(idle_deadline)),
+          if close_deadline.is_some() => {
+          trace::event!(debug, "close frame not acknowledged in time");
+          break Disconnect::Closed;
+        }
+      };
+
+      match event {
+        Event::PingTimeout => self.expire_pings(),
+        Event::Command(Some(command)) => {
+          self.execute(Some(&mut ws_stream), command).await;
+          if self.shutdown.is_some() {
+            break Disconnect::Closed;
+          }
+        }
+        Event::Command(None) => {
+          self.commands_open = false;
+          let _ = self.close(&mut ws_stream).await;
+        }
+        Event::Inbound(Some(Ok(Message::Ping(_)))) => {
+          // tungstenite queues the pong, flushing sends it right away
+          let _ = ws_stream.flush().await;
+        }
+        Event::Inbound(Some(Ok(Message::Pong(payload)))) => self.pong(&payload),
+        Event::Inbound(Some(Ok(msg))) => {
+          if let Message::Close(Some(frame)) = &msg {
+            trace::event!(
+              info,
+              code = u16::from(frame.code),
+              reason = %frame.reason,
+              "close frame received"
+            );
+            close_frame = Some(DisconnectReason::Closed {
+              code: frame.code.into(),
+              reason: frame.reason.to_string(),
+            });
+          }
+          let messages = process_message(msg, &self.config);
+          self.pending.extend(messages);
+        }
+        Event::Inbound(Some(Err(e))) if !self.closing => {
+          break Disconnect::Dropped(
+            close_frame.unwrap_or(DisconnectReason::Lost(Some(e.into()))),
+          );
+        }
+        Event::Inbound(_) if self.closing => break Disconnect::Closed,
+        Event::Inbound(_) => {
+          break Disconnect::Dropped(
+            close_frame.unwrap_or(DisconnectReason::Lost(None)),
+          )
+        }
+      }
+    };
+
+    for (_, (_, reply)) in self.pings.drain() {
+      let _ = reply.send(Err(KiteTickerError::NotConnected));
+    }
+    disconnect
+  }
+
+  /// Resolve the ping that carried this payload and record its round trip
+  fn pong(&mut self, payload: &[u8]) {
+    let id = payload.try_into().map(u64::from_be_bytes);
+    if let Some((sent, reply)) = id.ok().and_then(|id| self.pings.remove(&id)) {
+      let rtt = sent.elapsed();
+      *self.shared.last_rtt.lock().unwrap() = Some(rtt);
+      let _ = reply.send(Ok(rtt));
+    }
+  }
+
+  /// When the oldest unanswered ping times out
+  fn ping_deadline(&self) -> Option<Instant> {
+    let timeout = self.config.ping_timeout;
+    self.pings.values().map(|(sent, _)| *sent + timeout).min()
+  }
+
+  /// Fail the pings whose pong did not arrive in time
+  fn expire_pings(&mut self) {
+    let timeout = self.config.ping_timeout;
+    let expired = self
+      .pings
+      .iter()
+      .filter(|(_, (sent, _))| sent.elapsed() >= timeout)
+      .map(|(id, _)| *id)
+      .collect::<Vec<_>>();
+    for id in expired {
+      if let Some((_, reply)) = self.pings.remove(&id) {
+        trace::event!(warn, ?timeout, "ping timed out");
+        let _ = reply.send(Err(KiteTickerError::Timeout(timeout)));
+      }
+    }
+  }
+
+  /// Keep serving commands and consumers while disconnected, returns false
+  /// if the ticker got closed in the meantime
+  async fn wait(&mut self, delay: Duration) -> bool {
+    let sleep = tokio::time::sleep(delay);
+    tokio::pin!(sleep);
+    loop {
+      let command = tokio::select! {
+        _ = &mut sleep => return !self.closing,
+        command = self.commands.recv(), if self.commands_open => command,
+        permit = self.messages.reserve(), if !self.pending.is_empty() => {
+          match permit {
+            Ok(permit) => permit.send(self.pending.pop_front().unwrap()),
+            Err(_) => return false,
+          }
+          continue;
+        }
+      };
+      match command {
+        Some(command) => self.execute(None, command).await,
+        None => {
+          self.commands_open = false;
+          self.closing = true;
+        }
+      }
+      if self.closing {
+        return false;
+      }
+    }
+  }
+
+  /// Credentials for the next connection: the ones updated while
+  /// disconnected, then the provider's, then the ones used last
+  async fn next_credentials(&mut self) -> Result<Credentials, KiteTickerError> {
+    if let Some(credentials) = self.updated_credentials.take() {
+      return Ok(credentials);
+    }
+    match &self.config.credentials_provider {
+      Some(provider) => provider.credentials().await,
+      None => Ok(self.credentials.clone()),
+    }
+  }
+
+  /// Open a fresh connection and replay every subscribed token with its own
+  /// mode on it
+  async fn reopen(
+    &mut self,
+    credentials: Credentials,
+  ) -> Result<(WsStream, Vec<u32>), KiteTickerError> {
+    let mut ws_stream = self.config.open(&credentials).await?;
+    let mut by_mode: HashMap<Mode, Vec<u32>> = HashMap::new();
+    for (token, mode) in self.shared.subscriptions.read().unwrap().iter() {
+      by_mode.entry(mode.clone()).or_default().push(*token);
+    }
+    let mut tokens = vec![];
+    for (mode, mode_tokens) in by_mode {
+      send_subscribe(&mut ws_stream, &mode_tokens, mode).await?;
+      tokens.extend(mode_tokens);
+    }
+    self.credentials = credentials;
+    Ok((ws_stream, tokens))
+  }
+
+  /// Deliver whatever is left to the consumer before the stream ends
+  async fn flush(&mut self) {
+    while let Some(message) = self.pending.pop_front() {
+      if self.messages.send(message).await.is_err() {
+        break;
+      }
+    }
+  }
+
+  /// Deliver whatever is left to the consumer within the timeout, returns
+  /// how many messages were delivered and how many were dropped
+  async fn drain(&mut self, timeout: Duration) -> (usize, usize) {
+    let deadline = Instant::now() + timeout;
+    let total = self.pending.len();
+    let mut drained = 0;
+    while let Some(message) = self.pending.pop_front() {
+      let sent = tokio::time::timeout_at(deadline, self.messages.send(message));
+      match sent.await {
+        Ok(Ok(())) => drained += 1,
+        _ => break,
+      }
+    }
+    self.pending.clear();
+    (drained, total - drained)
+  }
+
+  /// Unsubscribe everything, close the connection with the given reason and
+  /// wait for the server to acknowledge it, keeping whatever arrives in the
+  /// meantime for the consumer
+  async fn close_gracefully(
+    &mut self,
+    ws_stream: Option<&mut WsStream>,
+    options: &ShutdownOptions,
+  ) -> ShutdownSummary {
+    self.closing = true;
+    let mut unsubscribed = self
+      .shared
+      .subscriptions
+      .write()
+      .unwrap()
+      .drain()
+      .map(|(token, _)| token)
+      .collect::<Vec<_>>();
+    unsubscribed.sort_unstable();
+    let mut summary = ShutdownSummary {
+      unsubscribed,
+      ..Default::default()
+    };
+    let Some(ws_stream) = ws_stream else {
+      return summary;
+    };
+
+    let unsubscribed = summary.unsubscribed.clone();
+    let pending = &mut self.pending;
+    let config = &self.config;
+    // the whole handshake is bounded, a peer that stops reading would
+    // otherwise hold up the unsubscribe or the close frame
+    let acknowledged = tokio::time::timeout(options.close_timeout, async {
+      if !unsubscribed.is_empty() {
+        let _ = send(ws_stream, Request::unsubscribe(unsubscribed)).await;
+      }
+      let frame = CloseFrame {
+        code: CloseCode::Normal,
+        reason: options.reason.clone().into(),
+      };
+      trace::event!(debug, reason = %options.reason, "sending close frame");
+      if ws_stream.close(Some(frame)).await.is_err() {
+        return false;
+      }
+      while let Some(Ok(message)) = ws_stream.next().await {
+        if let Message::Close(_) = message {
+          return true;
+        }
+        pending.extend(process_message(message, config));
+      }
+      false
+    });
+    summary.close_acknowledged = acknowledged.await.unwrap_or(false);
+    trace::event!(
+      debug,
+      acknowledged = summary.close_acknowledged,
+      "close handshake finished"
+    );
+    summary
+  }
+
+  /// get all tokens common between subscribed tokens and input tokens
+  /// and if the input is empty then all subscribed tokens
+  fn subscribed_or(&self, tokens: &[u32]) -> Vec<u32> {
+    let subscriptions = self.shared.subscriptions.read().unwrap();
+    if tokens.is_empty() {
+      subscriptions.keys().copied().collect()
+    } else {
+      tokens
+        .iter()
+        .filter(|t| subscriptions.contains_key(t))
+        .copied()
+        .collect()
+    }
+  }
+
+  /// Run a command against the live socket, or only record it while
+  /// disconnected so that it is replayed on reconnect
+  async fn execute(&mut self, ws_stream: Option<&mut WsStream>, cmd: Command) {
+    match cmd {
+      Command::Subscribe {
+        tokens,
+        mode,
+        reply,
+      } => {
+        let sent = match ws_stream {
+          Some(ws) => send_subscribe(ws, &tokens, mode.clone()).await,
+          None => Ok(()),
+        };
+        if sent.is_ok() {
+          let mut subscriptions = self.shared.subscriptions.write().unwrap();
+          subscriptions.extend(tokens.into_iter().map(|t| (t, mode.clone())));
+        }
+        let _ = reply.send(sent);
+      }
+      Command::SetMode {
+        tokens,
+        mode,
+        reply,
+      } => {
+        let tokens = self.subscribed_or(&tokens);
+        let sent = match ws_stream {
+          Some(ws) => {
+            send(ws, Request::mode(mode.clone(), tokens.clone())).await
+          }
+          None => Ok(()),
+        };
+        if sent.is_ok() {
+          let mut subscriptions = self.shared.subscriptions.write().unwrap();
+          for token in tokens {
+            subscriptions.insert(token, mode.clone());
+          }
+        }
+        let _ = reply.send(sent);
+      }
+      Command::Unsubscribe { tokens, reply } => {
+        let tokens = self.subscribed_or(&tokens);
+        let sent = match ws_stream {
+          Some(ws) => send(ws, Request::unsubscribe(tokens.clone())).await,
+          None => Ok(()),
+        };
+        if sent.is_ok() {
+          let mut subscriptions = self.shared.subscriptions.write().unwrap();
+          subscriptions.retain(|k, _| !tokens.contains(k));
+        }
+        let _ = reply.send(sent);
+      }
+      Command::Close { reply } => {
+        self.closing = true;
+        let closed = match ws_stream {
+          Some(ws) => self.close(ws).await,
+          None => Ok(()),
+        };
+        let _ = reply.send(closed);
+      }
+      Command::UpdateCredentials { credentials, reply } => match ws_stream {
+        Some(ws) => {
+          trace::event!(info, "reconnecting with updated credentials");
+          // the old connection keeps serving if the new one can not be
+          // opened
+          let swapped = match self.reopen(credentials).await {
+            Ok((stream, tokens)) => {
+              let mut previous = std::mem::replace(ws, stream);
+              let _ = previous.close(None).await;
+              for (_, (_, reply)) in self.pings.drain() {
+                let _ = reply.send(Err(KiteTickerError::NotConnected));
+              }
+              self.connected();
+              self
+                .pending
+                .push_back(Ok(TickerMessage::Resubscribed { tokens }));
+              Ok(())
+            }
+            Err(e) => Err(e),
+          };
+          let _ = reply.send(swapped);
+        }
+        None => {
+          self.credentials = credentials.clone();
+          self.updated_credentials = Some(credentials);
+          let _ = reply.send(Ok(()));
+        }
+      },
+      Command::Shutdown { options, reply } => {
+        let summary = self.close_gracefully(ws_stream, &options).await;
+        self.shutdown = Some((options, summary, reply));
+      }
+      Command::Ping { reply } => match ws_stream {
+        Some(ws) => {
+          let id = self.next_ping;
+          self.next_ping += 1;
+          let ping = Message::Ping(id.to_be_bytes().to_vec());
+          match ws.send(ping).await {
+            Ok(_) => {
+              self.pings.insert(id, (Instant::now(), reply));
+            }
+            Err(e) => {
+              let _ = reply.send(Err(e.into()));
+            }
+          }
+        }
+        None => {
+          let _ = reply.send(Err(KiteTickerError::NotConnected));
+        }
+      },
+    }
+  }
+}
+
+async fn send_subscribe(
+  ws_stream: &mut WsStream,
+  instrument_tokens: &[u32],
+  mode: Mode,
+) -> Result<(), KiteTickerError> {
+  let requests = [
+    Request::subscribe(instrument_tokens.to_vec()),
+    Request::mode(mode, instrument_tokens.to_vec()),
+  ];
+  let mut msgs = vec![];
+  for request in &requests {
+    trace_request(request);
+    msgs.push(Ok(Message::Text(serde_json::to_string(request)?)));
+  }
+  ws_stream.send_all(iter(msgs).by_ref()).await?;
+  Ok(())
+}
+
+async fn send(
+  ws_stream: &mut WsStream,
+  request: Request,
+) -> Result<(), KiteTickerError> {
+  trace_request(&request);
+  let request = serde_json::to_string(&request)?;
+  ws_stream.send(Message::Text(request)).await?;
+  Ok(())
+}
+
+#[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+fn trace_request(request: &Request) {
+  trace::event!(
+    debug,
+    action = request.a.as_str(),
+    tokens = request.v.token_count(),
+    mode = ?request.v.mode(),
+    "sending request"
+  );
+}
+
+fn process_message(
+  message: Message,
+  config: &TickerConfig,
+) -> Vec<Result<TickerMessage, KiteTickerError>> {
+  let metrics = &config.metrics;
+  match message {
+    Message::Text(text_message) => {
+      metrics.text_message(text_message.len());
+      trace::event!(debug, text = %text_message, "text message received");
+      process_text_message(text_message)
+        .map(Ok)
+        .into_iter()
+        .collect()
+    }
+    Message::Binary(binary_message) => {
+      let received_at = ist::now();
+      let frame = FrameView::new(&binary_message);
+      metrics.frame(binary_message.len(), frame.is_heartbeat());
+      if frame.is_heartbeat() {
+        vec![Ok(TickerMessage::Heartbeat)]
+      } else if config.raw_frames {
+        let ticks = frame
+          .packets()
+          .filter_map(Result::ok)
+          .map(|view| (view.instrument_token(), view.exchange_timestamp()));
+        metrics.ticks(ticks, received_at);
+        vec![Ok(TickerMessage::Frame {
+          bytes: binary_message,
+          received_at,
+        })]
+      } else {
+        let (ticks, errors) = process_binary(frame, received_at, metrics);
+        (!ticks.is_empty())
+          .then_some(Ok(TickerMessage::Ticks(ticks)))
+          .into_iter()
+          .chain(errors.into_iter().map(|e| Err(e.into())))
+          .collect()
+      }
+    }
+    Message::Close(closing_message) => closing_message
+      .map(|c| {
+        Ok(TickerMessage::ClosingMessage(json!({
+          "code": c.code.to_string(),
+          "reason": c.reason.to_string()
+        })))
+      })
+      .into_iter()
+      .collect(),
+    Message::Ping(_) | Message::Pong(_) | Message::Frame(_) => vec![],
+  }
+}
+
+/// Decode every packet of a frame, skipping over packets that can not be
+/// decoded as long as their length prefix is intact
+fn process_binary(
+  frame: FrameView<'_>,
+  received_at: DateTime<FixedOffset>,
+  metrics: &Metrics,
+) -> (Vec<TickMessage>, Vec<DecodeError>) {
+  let mut ticks = vec![];
+  let mut errors = vec![];
+  for packet in frame {
+    match packet.and_then(|view| view.to_tick()) {
+      Ok(tick) => {
+        ticks.push(TickMessage::new(tick.instrument_token, tick, received_at))
+      }
+      Err(e) => errors.push(e),
+    }
+  }
+  metrics.ticks(
+    ticks
+      .iter()
+      .map(|t| (t.instrument_token, t.content.exchange_timestamp)),
+    received_at,
+  );
+  metrics.decode_errors(errors.len());
+  #[cfg(feature = "tracing")]
+  for e in &errors {
+    tracing::warn!(
+      offset = e.offset,
+      error = %e,
+      excerpt = %trace::hex_excerpt(frame.as_bytes(), e.offset),
+      "failed to decode packet"
+    );
+  }
+  (ticks, errors)
+}
+
+fn process_text_message(text_message: String) -> Option<TickerMessage> {
+  serde_json::from_str::<TextMessage>(&text_message)
+    .map(|x| x.into())
+    .ok()
+}
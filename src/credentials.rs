@@ -0,0 +1,94 @@
+use std::{fmt, future::Future, pin::Pin};
+
+use crate::KiteTickerError;
+
+#[derive(Clone, PartialEq, Eq)]
+///
+/// API key and access token used to authenticate a connection
+///
+pub struct Credentials {
+  pub api_key: String,
+  pub access_token: String,
+}
+
+impl Credentials {
+  /// Credentials made of an API key and an access token
+  pub fn new(
+    api_key: impl Into<String>,
+    access_token: impl Into<String>,
+  ) -> Self {
+    Self {
+      api_key: api_key.into(),
+      access_token: access_token.into(),
+    }
+  }
+}
+
+impl fmt::Debug for Credentials {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    // enough of the API key to tell keys apart, never the whole of it
+    let chars = self.api_key.chars().count();
+    let api_key = match chars.checked_sub(4) {
+      Some(hidden) if hidden > 0 => {
+        format!(
+          "****{}",
+          self.api_key.chars().skip(hidden).collect::<String>()
+        )
+      }
+      _ => "<redacted>".to_string(),
+    };
+    f.debug_struct("Credentials")
+      .field("api_key", &api_key)
+      .field("access_token", &"<redacted>")
+      .finish()
+  }
+}
+
+/// Future returned by a [`CredentialsProvider`]
+pub type CredentialsFuture<'a> = Pin<
+  Box<dyn Future<Output = Result<Credentials, KiteTickerError>> + Send + 'a>,
+>;
+
+///
+/// Source of fresh credentials, asked before every connection attempt
+///
+/// Implemented for closures returning a future, so a provider reading the
+/// token refreshed by a daily login can be as short as
+///
+/// ```no_run
+/// use std::sync::{Arc, RwLock};
+/// use kiteticker_async::{Credentials, KiteTickerAsync};
+///
+/// # async fn run() -> Result<(), kiteticker_async::KiteTickerError> {
+/// let token = Arc::new(RwLock::new("access_token".to_string()));
+/// let ticker = KiteTickerAsync::builder()
+///   .credentials_provider(move || {
+///     let token = token.read().unwrap().clone();
+///     async move { Ok(Credentials::new("api_key", token)) }
+///   })
+///   .connect()
+///   .await?;
+/// # Ok(())
+/// # }
+/// ```
+///
+pub trait CredentialsProvider: Send + Sync {
+  /// Credentials for the next connection attempt
+  fn credentials(&self) -> CredentialsFuture<'_>;
+}
+
+impl<F, Fut> CredentialsProvider for F
+where
+  F: Fn() -> Fut + Send + Sync,
+  Fut: Future<Output = Result<Credentials, KiteTickerError>> + Send + 'static,
+{
+  fn credentials(&self) -> CredentialsFuture<'_> {
+    Box::pin(self())
+  }
+}
+
+impl fmt::Debug for dyn CredentialsProvider {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.write_str("CredentialsProvider")
+  }
+}
@@ -0,0 +1,276 @@
+use std::{fmt, sync::Arc, time::Duration};
+
+use tokio_tungstenite::tungstenite;
+
+#[derive(Debug, Clone)]
+///
+/// Errors returned by the ticker
+///
+/// Underlying errors are reference counted so that the error can be cloned
+/// along with the messages that carry it.
+///
+pub enum KiteTickerError {
+  /// Invalid settings such as missing credentials, a malformed endpoint or
+  /// a malformed header
+  Config(String),
+  /// The server rejected the WebSocket handshake, Kite answers invalid or
+  /// expired credentials with status 403
+  Handshake { status: u16, body: Option<String> },
+  /// The connection could not be established within the connect timeout
+  Timeout(Duration),
+  /// DNS, TCP, TLS or WebSocket protocol failure
+  Transport(Arc<tungstenite::Error>),
+  /// The proxy could not be reached or refused to open a tunnel
+  Proxy(String),
+  /// The server closed the connection with a close frame
+  Closed { code: u16, reason: String },
+  /// A request could not be serialized or a text message could not be parsed
+  Serialization(Arc<serde_json::Error>),
+  /// A binary packet could not be decoded
+  Decode(DecodeError),
+  /// The connection is down, or went down before the request completed
+  NotConnected,
+  /// The ticker was closed and no longer accepts requests
+  TickerClosed,
+  /// Subscribing would exceed the capacity of a [`crate::KiteTickerPool`]
+  PoolFull { requested: usize, available: usize },
+  /// A slow consumer missed this many messages because its buffer was full
+  Lagged(u64),
+}
+
+impl KiteTickerError {
+  /// Whether reconnecting could get past the error
+  pub fn is_retryable(&self) -> bool {
+    match self {
+      Self::Handshake { status, .. } => *status >= 500,
+      Self::Timeout(_)
+      | Self::Transport(_)
+      | Self::Proxy(_)
+      | Self::NotConnected => true,
+      Self::Closed { code, .. } => is_retryable_close(*code),
+      Self::Config(_)
+      | Self::Serialization(_)
+      | Self::Decode(_)
+      | Self::TickerClosed
+      | Self::PoolFull { .. }
+      | Self::Lagged(_) => false,
+    }
+  }
+}
+
+/// Close codes sent when the server goes away or is overloaded, as opposed
+/// to codes rejecting what the client sent
+fn is_retryable_close(code: u16) -> bool {
+  matches!(code, 1000 | 1001 | 1006 | 1011 | 1012 | 1013 | 1014)
+}
+
+impl fmt::Display for KiteTickerError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      Self::Config(reason) => write!(f, "invalid configuration: {}", reason),
+      Self::Handshake { status, body } => {
+        write!(f, "handshake rejected with status {}", status)?;
+        match body {
+          Some(body) => write!(f, ": {}", body),
+          None => Ok(()),
+        }
+      }
+      Self::Timeout(timeout) => {
+        write!(f, "connection timed out after {:?}", timeout)
+      }
+      Self::Transport(e) => write!(f, "transport error: {}", e),
+      Self::Proxy(reason) => write!(f, "proxy error: {}", reason),
+      Self::Closed { code, reason } => {
+        write!(f, "connection closed with code {}: {}", code, reason)
+      }
+      Self::Serialization(e) => write!(f, "serialization error: {}", e),
+      Self::Decode(e) => write!(f, "failed to decode packet: {}", e),
+      Self::NotConnected => f.write_str("not connected"),
+      Self::TickerClosed => f.write_str("ticker is closed"),
+      Self::PoolFull {
+        requested,
+        available,
+      } => write!(
+        f,
+        "cannot subscribe {} new tokens, the pool has room for {}",
+        requested, available
+      ),
+      Self::Lagged(skipped) => {
+        write!(f, "consumer lagged behind and missed {} messages", skipped)
+      }
+    }
+  }
+}
+
+impl std::error::Error for KiteTickerError {
+  fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+    match self {
+      Self::Transport(e) => Some(e.as_ref()),
+      Self::Serialization(e) => Some(e.as_ref()),
+      Self::Decode(e) => Some(e),
+      _ => None,
+    }
+  }
+}
+
+impl From<tungstenite::Error> for KiteTickerError {
+  fn from(value: tungstenite::Error) -> Self {
+    match value {
+      tungstenite::Error::Http(response) => Self::Handshake {
+        status: response.status().as_u16(),
+        body: response
+          .body()
+          .as_ref()
+          .map(|b| String::from_utf8_lossy(b).into_owned()),
+      },
+      e => Self::Transport(Arc::new(e)),
+    }
+  }
+}
+
+impl From<serde_json::Error> for KiteTickerError {
+  fn from(value: serde_json::Error) -> Self {
+    Self::Serialization(Arc::new(value))
+  }
+}
+
+impl From<DecodeError> for KiteTickerError {
+  fn from(value: DecodeError) -> Self {
+    Self::Decode(value)
+  }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+///
+/// Binary packet that could not be decoded
+///
+/// The offset is counted from the start of the decoded input, which is the
+/// whole frame for packets reported by the ticker.
+///
+pub struct DecodeError {
+  pub offset: usize,
+  pub reason: DecodeReason,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+///
+/// Why a binary packet could not be decoded
+///
+pub enum DecodeReason {
+  /// A field `needed` bytes long had only `available` bytes left
+  Truncated { needed: usize, available: usize },
+  /// Packet length that matches no mode
+  UnknownLength(usize),
+  /// A field that can not be negative was negative
+  Negative(&'static str),
+}
+
+impl DecodeError {
+  pub(crate) fn new(offset: usize, reason: DecodeReason) -> Self {
+    Self { offset, reason }
+  }
+
+  pub(crate) fn truncated(offset: usize, needed: usize, input: &[u8]) -> Self {
+    Self::new(
+      offset,
+      DecodeReason::Truncated {
+        needed,
+        available: input.len().saturating_sub(offset),
+      },
+    )
+  }
+
+  /// Shift the offset of an error found in a slice starting at `base`
+  pub(crate) fn at(mut self, base: usize) -> Self {
+    self.offset += base;
+    self
+  }
+}
+
+impl fmt::Display for DecodeError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match &self.reason {
+      DecodeReason::Truncated { needed, available } => write!(
+        f,
+        "needed {} bytes at offset {} but only {} are left",
+        needed, self.offset, available
+      ),
+      DecodeReason::UnknownLength(length) => {
+        write!(
+          f,
+          "unknown packet length {} at offset {}",
+          length, self.offset
+        )
+      }
+      DecodeReason::Negative(field) => {
+        write!(f, "negative {} at offset {}", field, self.offset)
+      }
+    }
+  }
+}
+
+impl std::error::Error for DecodeError {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+///
+/// Tick that could not be encoded as a binary packet
+///
+pub struct EncodeError {
+  pub field: &'static str,
+  pub reason: EncodeReason,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+///
+/// Why a tick could not be encoded
+///
+pub enum EncodeReason {
+  /// The value does not fit in the field, or would decode as unset
+  OutOfRange,
+  /// The value has more decimals than the field holds
+  Precision,
+}
+
+impl EncodeError {
+  pub(crate) fn new(field: &'static str, reason: EncodeReason) -> Self {
+    Self { field, reason }
+  }
+}
+
+impl fmt::Display for EncodeError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self.reason {
+      EncodeReason::OutOfRange => {
+        write!(f, "{} does not fit in a packet", self.field)
+      }
+      EncodeReason::Precision => {
+        write!(f, "{} has more decimals than a packet holds", self.field)
+      }
+    }
+  }
+}
+
+impl std::error::Error for EncodeError {}
+
+#[cfg(test)]
+mod tests {
+  use super::KiteTickerError;
+
+  #[test]
+  fn test_retryable() {
+    let closed = |code| KiteTickerError::Closed {
+      code,
+      reason: String::new(),
+    };
+    assert!(closed(1001).is_retryable());
+    assert!(closed(1011).is_retryable());
+    assert!(!closed(1008).is_retryable());
+    assert!(!closed(4003).is_retryable());
+
+    let handshake = |status| KiteTickerError::Handshake { status, body: None };
+    assert!(!handshake(403).is_retryable());
+    assert!(handshake(503).is_retryable());
+    assert!(KiteTickerError::NotConnected.is_retryable());
+    assert!(!KiteTickerError::TickerClosed.is_retryable());
+  }
+}
@@ -14,14 +14,18 @@
 //! This crate provides types to subscribe and receive live quotes for instruments during market hours via WebSockets.
 //! The response is parsed and converted into Rust types.
 //! The WebSocket connection is managed by the library and reconnected automatically.
+//! Reconnection follows a [`ReconnectPolicy`] with exponential backoff, and every
+//! subscribed token is replayed with its own [`Mode`] once the connection is back.
+//! More instruments than a single connection allows can be spread over several
+//! connections with a [`KiteTickerPool`].
 //!
 //! # Usage
 //! ```
 //!
-//! use kiteticker_async::{KiteTickerAsync, Mode, TickerMessage};
+//! use kiteticker_async::{KiteTickerAsync, KiteTickerError, Mode, TickerMessage};
 //!
 //! #[tokio::main]
-//! pub async fn main() -> Result<(), String> {
+//! pub async fn main() -> Result<(), KiteTickerError> {
 //!   let api_key = std::env::var("KITE_API_KEY").unwrap_or_default();
 //!   let access_token = std::env::var("KITE_ACCESS_TOKEN").unwrap_or_default();
 //!   let ticker = KiteTickerAsync::connect(&api_key, &access_token).await?;
@@ -49,11 +53,50 @@
 //!   Ok(())
 //! }
 //! ```
+mod broadcast;
+mod builder;
+mod conflator;
+mod connection;
+mod credentials;
+mod error;
+pub use broadcast::{BroadcastReceiver, KiteTickerBroadcast};
+pub use builder::{KiteTickerAsyncBuilder, DEFAULT_ENDPOINT};
+pub use conflator::KiteTickerConflator;
+pub use credentials::{Credentials, CredentialsFuture, CredentialsProvider};
+pub use error::{
+  DecodeError, DecodeReason, EncodeError, EncodeReason, KiteTickerError,
+};
+
 mod models;
 pub use models::{
-  Depth, DepthItem, Exchange, Mode, Order, OrderStatus, OrderTransactionType,
-  OrderValidity, Request, TextMessage, Tick, TickMessage, TickerMessage, OHLC,
+  Depth, DepthItem, DisconnectReason, Exchange, FrameView, Mode, Order,
+  OrderStatus, OrderTransactionType, OrderValidity, Packets, Price, Request,
+  TextMessage, Tick, TickMessage, TickView, TickerMessage, OHLC,
 };
 
 pub mod ticker;
-pub use ticker::{KiteTickerAsync, KiteTickerSubscriber};
+
+#[cfg(feature = "testing")]
+pub mod testing;
+pub use ticker::{
+  ConnectionState, KiteTickerAsync, KiteTickerHandle, KiteTickerSubscriber,
+  ReconnectPolicy, ShutdownOptions, ShutdownSummary,
+};
+pub use tokio_util::sync::CancellationToken;
+
+mod tls;
+pub use tls::{Certificate, Connector};
+
+mod metrics;
+mod trace;
+#[cfg(feature = "metrics-server")]
+pub use metrics::MetricsServer;
+pub use metrics::{LatencyHistogram, Metrics, MetricsSnapshot};
+
+mod pool;
+mod proxy;
+pub use pool::{KiteTickerPool, PoolLimits, ShardMessage, ShardStatus};
+pub use proxy::Proxy;
+
+mod router;
+pub use router::{KiteTickerRouter, TickReceiver};
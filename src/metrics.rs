@@ -0,0 +1,399 @@
+use std::{
+  collections::HashMap,
+  fmt::Write,
+  sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc, Mutex,
+  },
+  time::Duration,
+};
+
+use chrono::{DateTime, FixedOffset};
+
+/// Upper bounds of the exchange to receive latency buckets, exchange
+/// timestamps only have a resolution of one second
+const LATENCY_BUCKETS: [Duration; 8] = [
+  Duration::from_millis(250),
+  Duration::from_millis(500),
+  Duration::from_secs(1),
+  Duration::from_secs(2),
+  Duration::from_secs(5),
+  Duration::from_secs(10),
+  Duration::from_secs(30),
+  Duration::from_secs(60),
+];
+
+#[derive(Debug, Default)]
+struct Registry {
+  frames: AtomicU64,
+  heartbeats: AtomicU64,
+  text_messages: AtomicU64,
+  bytes: AtomicU64,
+  ticks: AtomicU64,
+  decode_errors: AtomicU64,
+  reconnect_attempts: AtomicU64,
+  reconnects: AtomicU64,
+  ticks_per_token: Mutex<HashMap<u32, u64>>,
+  latency: Mutex<Histogram>,
+}
+
+#[derive(Debug, Default)]
+struct Histogram {
+  buckets: [u64; LATENCY_BUCKETS.len()],
+  count: u64,
+  sum: Duration,
+}
+
+#[derive(Debug, Clone, Default)]
+///
+/// Counters of what a ticker received, cheap to clone and shared by every
+/// clone
+///
+/// A ticker keeps its own registry, readable with
+/// [`crate::KiteTickerHandle::metrics`]. Passing the same registry to
+/// several builders, or to the builder of a [`crate::KiteTickerPool`],
+/// aggregates their connections.
+///
+pub struct Metrics {
+  registry: Arc<Registry>,
+}
+
+impl Metrics {
+  /// Empty registry
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Current value of every counter
+  pub fn snapshot(&self) -> MetricsSnapshot {
+    let r = &self.registry;
+    let latency = r.latency.lock().unwrap();
+    let mut cumulative = 0;
+    let buckets = LATENCY_BUCKETS
+      .iter()
+      .zip(latency.buckets)
+      .map(|(bound, count)| {
+        cumulative += count;
+        (*bound, cumulative)
+      })
+      .collect();
+    MetricsSnapshot {
+      frames: r.frames.load(Ordering::Relaxed),
+      heartbeats: r.heartbeats.load(Ordering::Relaxed),
+      text_messages: r.text_messages.load(Ordering::Relaxed),
+      bytes: r.bytes.load(Ordering::Relaxed),
+      ticks: r.ticks.load(Ordering::Relaxed),
+      ticks_per_token: r.ticks_per_token.lock().unwrap().clone(),
+      decode_errors: r.decode_errors.load(Ordering::Relaxed),
+      reconnect_attempts: r.reconnect_attempts.load(Ordering::Relaxed),
+      reconnects: r.reconnects.load(Ordering::Relaxed),
+      latency: LatencyHistogram {
+        buckets,
+        count: latency.count,
+        sum: latency.sum,
+      },
+    }
+  }
+
+  /// Current value of every counter in the Prometheus text format
+  pub fn render_prometheus(&self) -> String {
+    self.snapshot().to_prometheus()
+  }
+
+  /// Serve the metrics in the Prometheus text format over HTTP at
+  /// `/metrics` until the returned server is dropped
+  #[cfg(feature = "metrics-server")]
+  pub async fn serve(
+    &self,
+    addr: impl tokio::net::ToSocketAddrs,
+  ) -> std::io::Result<MetricsServer> {
+    let listener = tokio::net::TcpListener::bind(addr).await?;
+    let local_addr = listener.local_addr()?;
+    let metrics = self.clone();
+    let task = tokio::spawn(async move {
+      while let Ok((stream, _)) = listener.accept().await {
+        tokio::spawn(respond(stream, metrics.clone()));
+      }
+    });
+    Ok(MetricsServer { local_addr, task })
+  }
+
+  /// Binary frame received, heartbeats included
+  pub(crate) fn frame(&self, bytes: usize, heartbeat: bool) {
+    let r = &self.registry;
+    r.frames.fetch_add(1, Ordering::Relaxed);
+    r.bytes.fetch_add(bytes as u64, Ordering::Relaxed);
+    if heartbeat {
+      r.heartbeats.fetch_add(1, Ordering::Relaxed);
+    }
+  }
+
+  /// Text message received
+  pub(crate) fn text_message(&self, bytes: usize) {
+    let r = &self.registry;
+    r.text_messages.fetch_add(1, Ordering::Relaxed);
+    r.bytes.fetch_add(bytes as u64, Ordering::Relaxed);
+  }
+
+  /// Instrument token and exchange timestamp of the ticks of a frame
+  /// received at `received`
+  pub(crate) fn ticks(
+    &self,
+    ticks: impl IntoIterator<Item = (u32, Option<DateTime<FixedOffset>>)>,
+    received: DateTime<FixedOffset>,
+  ) {
+    let r = &self.registry;
+    let mut per_token = r.ticks_per_token.lock().unwrap();
+    let mut latency = r.latency.lock().unwrap();
+    for (token, exchange_timestamp) in ticks {
+      r.ticks.fetch_add(1, Ordering::Relaxed);
+      *per_token.entry(token).or_default() += 1;
+      let Some(exchange) = exchange_timestamp else {
+        continue;
+      };
+      // clocks drifting apart can put the exchange ahead of us
+      let elapsed = (received - exchange).to_std().unwrap_or_default();
+      if let Some(i) = LATENCY_BUCKETS.iter().position(|b| elapsed <= *b) {
+        latency.buckets[i] += 1;
+      }
+      latency.count += 1;
+      latency.sum += elapsed;
+    }
+  }
+
+  pub(crate) fn decode_errors(&self, count: usize) {
+    let r = &self.registry;
+    r.decode_errors.fetch_add(count as u64, Ordering::Relaxed);
+  }
+
+  pub(crate) fn reconnect_attempt(&self) {
+    let r = &self.registry;
+    r.reconnect_attempts.fetch_add(1, Ordering::Relaxed);
+  }
+
+  pub(crate) fn reconnect(&self) {
+    self.registry.reconnects.fetch_add(1, Ordering::Relaxed);
+  }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+///
+/// Value of every counter of a [`Metrics`] registry at one point in time
+///
+pub struct MetricsSnapshot {
+  /// Binary frames received, heartbeats included
+  pub frames: u64,
+  /// Heartbeat frames received
+  pub heartbeats: u64,
+  /// Text messages received, such as order postbacks and errors
+  pub text_messages: u64,
+  /// Payload bytes of every frame and text message received
+  pub bytes: u64,
+  /// Ticks decoded
+  pub ticks: u64,
+  /// Ticks decoded for each instrument token
+  pub ticks_per_token: HashMap<u32, u64>,
+  /// Packets that could not be decoded
+  pub decode_errors: u64,
+  /// Reconnect attempts, failed ones included
+  pub reconnect_attempts: u64,
+  /// Connections re-established after a drop
+  pub reconnects: u64,
+  /// Time between the exchange timestamp of a tick and its frame being
+  /// received, for ticks carrying one
+  pub latency: LatencyHistogram,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+///
+/// Cumulative histogram of latencies
+///
+pub struct LatencyHistogram {
+  /// Upper bound of every bucket along with the number of latencies at or
+  /// below it
+  pub buckets: Vec<(Duration, u64)>,
+  /// Number of latencies recorded
+  pub count: u64,
+  /// Sum of the latencies recorded
+  pub sum: Duration,
+}
+
+impl MetricsSnapshot {
+  /// Every counter in the Prometheus text format
+  pub fn to_prometheus(&self) -> String {
+    let mut out = String::new();
+    let counters = [
+      ("frames", "Binary frames received", self.frames),
+      ("heartbeats", "Heartbeat frames received", self.heartbeats),
+      (
+        "text_messages",
+        "Text messages received",
+        self.text_messages,
+      ),
+      ("received_bytes", "Payload bytes received", self.bytes),
+      ("ticks", "Ticks decoded", self.ticks),
+      (
+        "decode_errors",
+        "Packets that failed to decode",
+        self.decode_errors,
+      ),
+      (
+        "reconnect_attempts",
+        "Reconnect attempts",
+        self.reconnect_attempts,
+      ),
+      ("reconnects", "Successful reconnects", self.reconnects),
+    ];
+    for (name, help, value) in counters {
+      let _ = writeln!(out, "# HELP kiteticker_{}_total {}", name, help);
+      let _ = writeln!(out, "# TYPE kiteticker_{}_total counter", name);
+      let _ = writeln!(out, "kiteticker_{}_total {}", name, value);
+    }
+
+    let _ = writeln!(
+      out,
+      "# HELP kiteticker_instrument_ticks_total Ticks decoded per instrument"
+    );
+    let _ = writeln!(out, "# TYPE kiteticker_instrument_ticks_total counter");
+    let mut per_token = self.ticks_per_token.iter().collect::<Vec<_>>();
+    per_token.sort_unstable();
+    for (token, value) in per_token {
+      let _ = writeln!(
+        out,
+        "kiteticker_instrument_ticks_total{{instrument_token=\"{}\"}} {}",
+        token, value
+      );
+    }
+
+    let name = "kiteticker_tick_latency_seconds";
+    let _ = writeln!(
+      out,
+      "# HELP {} Exchange timestamp to receive latency of ticks",
+      name
+    );
+    let _ = writeln!(out, "# TYPE {} histogram", name);
+    for (bound, count) in &self.latency.buckets {
+      let _ = writeln!(
+        out,
+        "{}_bucket{{le=\"{}\"}} {}",
+        name,
+        bound.as_secs_f64(),
+        count
+      );
+    }
+    let _ =
+      writeln!(out, "{}_bucket{{le=\"+Inf\"}} {}", name, self.latency.count);
+    let _ = writeln!(out, "{}_sum {}", name, self.latency.sum.as_secs_f64());
+    let _ = writeln!(out, "{}_count {}", name, self.latency.count);
+    out
+  }
+}
+
+#[cfg(feature = "metrics-server")]
+#[derive(Debug)]
+///
+/// HTTP endpoint started by [`Metrics::serve`], stopped when dropped
+///
+pub struct MetricsServer {
+  local_addr: std::net::SocketAddr,
+  task: tokio::task::JoinHandle<()>,
+}
+
+#[cfg(feature = "metrics-server")]
+impl MetricsServer {
+  /// Address the endpoint listens on
+  pub fn local_addr(&self) -> std::net::SocketAddr {
+    self.local_addr
+  }
+}
+
+#[cfg(feature = "metrics-server")]
+impl Drop for MetricsServer {
+  fn drop(&mut self) {
+    self.task.abort();
+  }
+}
+
+/// Answer a single request, `GET /metrics` with the metrics and anything
+/// else with 404
+#[cfg(feature = "metrics-server")]
+async fn respond(mut stream: tokio::net::TcpStream, metrics: Metrics) {
+  use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+  let mut head = vec![];
+  let mut buf = [0; 1024];
+  while !head.windows(4).any(|w| w == b"\r\n\r\n") {
+    match stream.read(&mut buf).await {
+      Ok(0) | Err(_) => return,
+      Ok(n) if head.len() + n > 8192 => return,
+      Ok(n) => head.extend_from_slice(&buf[..n]),
+    }
+  }
+  let head = String::from_utf8_lossy(&head);
+  let mut request_line = head.lines().next().unwrap_or_default().split(' ');
+  let (status, body) = match (request_line.next(), request_line.next()) {
+    (Some("GET"), Some("/metrics")) => ("200 OK", metrics.render_prometheus()),
+    _ => ("404 Not Found", String::new()),
+  };
+  let response = format!(
+    "HTTP/1.1 {}\r\nContent-Type: text/plain; version=0.0.4\r\n\
+     Content-Length: {}\r\nConnection: close\r\n\r\n{}",
+    status,
+    body.len(),
+    body
+  );
+  let _ = stream.write_all(response.as_bytes()).await;
+  let _ = stream.shutdown().await;
+}
+
+#[cfg(test)]
+mod tests {
+  use std::time::Duration;
+
+  use super::Metrics;
+  use crate::models::ist::from_seconds;
+
+  #[test]
+  fn test_prometheus() {
+    let metrics = Metrics::new();
+    metrics.frame(10, true);
+    metrics.frame(100, false);
+    metrics.text_message(20);
+    metrics.decode_errors(1);
+    let received = from_seconds(1_700_000_002);
+    metrics.ticks(
+      [
+        (408065, Some(from_seconds(1_700_000_000))),
+        (408065, Some(from_seconds(1_700_000_002))),
+        (256265, None),
+      ],
+      received,
+    );
+
+    let snapshot = metrics.snapshot();
+    assert_eq!(snapshot.frames, 2);
+    assert_eq!(snapshot.bytes, 130);
+    assert_eq!(snapshot.ticks, 3);
+    assert_eq!(snapshot.ticks_per_token[&408065], 2);
+    assert_eq!(snapshot.latency.count, 2);
+    assert_eq!(snapshot.latency.sum, Duration::from_secs(2));
+    assert_eq!(snapshot.latency.buckets[0], (Duration::from_millis(250), 1));
+    assert_eq!(snapshot.latency.buckets[3], (Duration::from_secs(2), 2));
+
+    let text = snapshot.to_prometheus();
+    for line in [
+      "kiteticker_frames_total 2",
+      "kiteticker_heartbeats_total 1",
+      "kiteticker_received_bytes_total 130",
+      "kiteticker_decode_errors_total 1",
+      "kiteticker_instrument_ticks_total{instrument_token=\"256265\"} 1",
+      "kiteticker_instrument_ticks_total{instrument_token=\"408065\"} 2",
+      "kiteticker_tick_latency_seconds_bucket{le=\"0.25\"} 1",
+      "kiteticker_tick_latency_seconds_bucket{le=\"+Inf\"} 2",
+      "kiteticker_tick_latency_seconds_sum 2",
+      "kiteticker_tick_latency_seconds_count 2",
+    ] {
+      assert!(text.lines().any(|l| l == line), "missing {}", line);
+    }
+  }
+}
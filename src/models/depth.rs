@@ -1,8 +1,9 @@
-use crate::Exchange;
+use crate::{DecodeError, DecodeReason, EncodeError, Exchange, Price};
 
-use super::{value, price, value_short};
+use super::{price, put_price, put_value, put_value_short, value, value_short};
 
 #[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 ///
 /// Market depth packet structure
 ///
@@ -12,50 +13,75 @@ pub struct Depth {
 }
 
 impl Depth {
-  pub(crate) fn from(input: &[u8], exchange: &Exchange) -> Option<Self> {
-    if let Some(bs) = input.get(0..120) {
-      let parse_depth_item = |v: &[u8], start: usize| {
-        v.get(start..start + 10)
-          .and_then(|xs| DepthItem::from(xs, exchange))
-          .unwrap_or_default()
-      };
-      let mut depth = Depth::default();
-      for i in 0..5 {
-        let start = i * 12;
-        depth.buy[i] = parse_depth_item(bs, start)
-      }
-      for i in 0..5 {
-        let start = 60 + i * 12;
-        depth.sell[i] = parse_depth_item(bs, start);
-      }
-
-      Some(depth)
-    } else {
-      None
+  pub(crate) fn from(
+    input: &[u8],
+    exchange: &Exchange,
+  ) -> Result<Self, DecodeError> {
+    let bs = input
+      .get(0..120)
+      .ok_or_else(|| DecodeError::truncated(0, 120, input))?;
+    let parse_depth_item = |start: usize| {
+      DepthItem::from(&bs[start..start + 10], exchange).map_err(|e| e.at(start))
+    };
+    let mut depth = Depth::default();
+    for i in 0..5 {
+      depth.buy[i] = parse_depth_item(i * 12)?;
     }
+    for i in 0..5 {
+      depth.sell[i] = parse_depth_item(60 + i * 12)?;
+    }
+
+    Ok(depth)
+  }
+
+  pub(crate) fn write(
+    &self,
+    out: &mut Vec<u8>,
+    exchange: &Exchange,
+  ) -> Result<(), EncodeError> {
+    for item in self.buy.iter().chain(self.sell.iter()) {
+      item.write(out, exchange)?;
+    }
+    Ok(())
   }
 }
 
 #[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 ///
 /// Structure for each market depth entry
 ///
 pub struct DepthItem {
+  #[cfg_attr(feature = "serde", serde(rename = "quantity"))]
   pub qty: u32,
-  pub price: f64,
+  pub price: Price,
   pub orders: u16,
 }
 
 impl DepthItem {
-  pub fn from(input: &[u8], exchange: &Exchange) -> Option<Self> {
-    if let Some(bs) = input.get(0..10) {
-      Some(DepthItem {
-        qty: value(&bs[0..=3]).unwrap(),
-        price: price(&bs[4..=7], exchange).unwrap(),
-        orders: value_short(&bs[8..=9]).unwrap(),
-      })
-    } else {
-      None
-    }
+  pub fn from(input: &[u8], exchange: &Exchange) -> Result<Self, DecodeError> {
+    let bs = input
+      .get(0..10)
+      .ok_or_else(|| DecodeError::truncated(0, 10, input))?;
+    Ok(DepthItem {
+      qty: value(&bs[0..=3])
+        .ok_or(DecodeError::new(0, DecodeReason::Negative("quantity")))?,
+      price: price(&bs[4..=7], exchange).unwrap_or_default(),
+      orders: value_short(&bs[8..=9])
+        .ok_or(DecodeError::new(8, DecodeReason::Negative("orders")))?,
+    })
+  }
+
+  pub(crate) fn write(
+    &self,
+    out: &mut Vec<u8>,
+    exchange: &Exchange,
+  ) -> Result<(), EncodeError> {
+    put_value(out, self.qty, "depth quantity")?;
+    put_price(out, self.price, exchange, "depth price")?;
+    put_value_short(out, self.orders, "depth orders")?;
+    // 10 - 12 bytes : padding
+    out.extend([0, 0]);
+    Ok(())
   }
 }
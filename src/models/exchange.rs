@@ -1,4 +1,5 @@
 #[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 ///
 /// Exchange options
 ///
@@ -16,19 +17,17 @@ pub enum Exchange {
 }
 
 impl Exchange {
-  pub(crate) fn divisor(&self) -> f64 {
+  /// Decimals of the prices of the segment
+  pub fn scale(&self) -> u32 {
     match self {
-      Self::CDS => 100_000_0.0,
-      Self::BCD => 100_0.0,
-      _ => 100.0,
+      Self::CDS => 6,
+      Self::BCD => 3,
+      _ => 2,
     }
   }
 
   pub(crate) fn is_tradable(&self) -> bool {
-    match self {
-      Self::INDICES => false,
-      _ => true,
-    }
+    !matches!(self, Self::INDICES)
   }
 }
 
@@ -50,21 +49,20 @@ impl From<usize> for Exchange {
 }
 
 impl From<String> for Exchange {
-    fn from(value: String) -> Self {
-        match value.as_str() {
-            "NSE" => Self::NSE,
-            "NFO" => Self::NFO,
-            "CDS" => Self::CDS,
-            "BSE" => Self::BSE,
-            "BFO" => Self::BFO,
-            "BCD" => Self::BCD,
-            "MCX" => Self::MCX,
-            "MCXSX" => Self::MCXSX,
-            "INDICES" => Self::INDICES,
-            _ => Self::NSE,
-
-        }
+  fn from(value: String) -> Self {
+    match value.as_str() {
+      "NSE" => Self::NSE,
+      "NFO" => Self::NFO,
+      "CDS" => Self::CDS,
+      "BSE" => Self::BSE,
+      "BFO" => Self::BFO,
+      "BCD" => Self::BCD,
+      "MCX" => Self::MCX,
+      "MCXSX" => Self::MCXSX,
+      "INDICES" => Self::INDICES,
+      _ => Self::NSE,
     }
+  }
 }
 
 impl From<Exchange> for String {
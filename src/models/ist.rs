@@ -0,0 +1,31 @@
+//! India Standard Time, the time zone of the exchanges and of the
+//! timestamps on ticks
+
+use chrono::{DateTime, FixedOffset, Utc};
+
+/// UTC+05:30
+pub(crate) fn offset() -> FixedOffset {
+  FixedOffset::east_opt(5 * 3600 + 30 * 60).expect("valid offset")
+}
+
+/// Current time, as stamped on received frames
+pub(crate) fn now() -> DateTime<FixedOffset> {
+  Utc::now().with_timezone(&offset())
+}
+
+/// Seconds since the epoch, as sent in packets
+pub(crate) fn from_seconds(secs: u32) -> DateTime<FixedOffset> {
+  DateTime::from_timestamp(secs.into(), 0)
+    .expect("u32 seconds are in range")
+    .with_timezone(&offset())
+}
+
+/// Write a UTC timestamp as RFC 3339 in IST
+#[cfg(feature = "serde")]
+pub(crate) fn serialize<S: serde::Serializer>(
+  at: &DateTime<Utc>,
+  s: S,
+) -> Result<S::Ok, S::Error> {
+  use serde::Serialize;
+  at.with_timezone(&offset()).to_rfc3339().serialize(s)
+}
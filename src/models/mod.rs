@@ -1,15 +1,18 @@
-use std::ops::Div;
-
 mod depth;
 mod exchange;
+pub(crate) mod ist;
 mod mode;
 mod ohlc;
 mod order;
+mod price;
 mod request;
 mod text_message;
 mod tick;
+#[cfg(feature = "serde")]
+mod tick_json;
 mod tick_message;
 mod ticker_message;
+mod view;
 pub use self::depth::{Depth, DepthItem};
 pub use self::exchange::Exchange;
 pub use self::mode::Mode;
@@ -17,31 +20,119 @@ pub use self::ohlc::OHLC;
 pub use self::order::{
   Order, OrderStatus, OrderTransactionType, OrderValidity,
 };
+pub use self::price::Price;
 pub use self::request::Request;
+#[cfg(feature = "testing")]
+pub(crate) use self::request::{RequestActions, RequestData};
 pub use self::text_message::TextMessage;
 pub use self::tick::Tick;
 pub use self::tick_message::TickMessage;
-pub use self::ticker_message::TickerMessage;
+pub use self::ticker_message::{DisconnectReason, TickerMessage};
+pub use self::view::{FrameView, Packets, TickView};
+
+use chrono::{DateTime, FixedOffset};
+
+use crate::{EncodeError, EncodeReason};
 
 fn value(input: &[u8]) -> Option<u32> {
-  let value = i32::from_be_bytes(input[0..=3].try_into().unwrap());
+  let value = i32::from_be_bytes(input.get(0..4)?.try_into().ok()?);
   value.try_into().ok()
 }
 
 fn value_short(input: &[u8]) -> Option<u16> {
-  let value = i16::from_be_bytes(input[0..=1].try_into().unwrap());
+  let value = i16::from_be_bytes(input.get(0..2)?.try_into().ok()?);
   value.try_into().ok()
 }
 
-fn price(input: &[u8], exchange: &Exchange) -> Option<f64> {
-  let value = i32::from_be_bytes(input[0..4].try_into().unwrap()) as f64;
-  if exchange.divisor() > 0_f64 {
-    Some(value.div(exchange.divisor()))
-  } else {
-    None
+fn timestamp(input: &[u8]) -> Option<DateTime<FixedOffset>> {
+  value(input).map(ist::from_seconds)
+}
+
+fn price(input: &[u8], exchange: &Exchange) -> Option<Price> {
+  let value = i32::from_be_bytes(input.get(0..4)?.try_into().ok()?);
+  Some(Price::new(value.into(), exchange.scale()))
+}
+
+/// Mode of a packet of `len` bytes, `None` for a length that matches no
+/// layout such as a truncated packet
+fn packet_mode(len: usize, is_index: bool) -> Option<Mode> {
+  match (len, is_index) {
+    (8, _) => Some(Mode::LTP),
+    (28, true) | (44, false) => Some(Mode::Quote),
+    (32, true) | (184, false) => Some(Mode::Full),
+    _ => None,
   }
 }
 
-pub(crate) fn packet_length(bs: &[u8]) -> usize {
-  i16::from_be_bytes(bs[0..=1].try_into().unwrap()) as usize
+pub(crate) fn packet_length(bs: &[u8]) -> Option<usize> {
+  Some(u16::from_be_bytes(bs.get(0..2)?.try_into().ok()?) as usize)
+}
+
+/// Write a length prefixed packet count followed by the packets
+pub(crate) fn frame<P: AsRef<[u8]>>(packets: &[P]) -> Vec<u8> {
+  let mut frame = (packets.len() as u16).to_be_bytes().to_vec();
+  for packet in packets {
+    frame.extend((packet.as_ref().len() as u16).to_be_bytes());
+    frame.extend(packet.as_ref());
+  }
+  frame
+}
+
+fn put_value(
+  out: &mut Vec<u8>,
+  value: u32,
+  field: &'static str,
+) -> Result<(), EncodeError> {
+  // negative values decode as unset, so the top bit is not available
+  let value = i32::try_from(value)
+    .map_err(|_| EncodeError::new(field, EncodeReason::OutOfRange))?;
+  out.extend(value.to_be_bytes());
+  Ok(())
+}
+
+fn put_value_short(
+  out: &mut Vec<u8>,
+  value: u16,
+  field: &'static str,
+) -> Result<(), EncodeError> {
+  let value = i16::try_from(value)
+    .map_err(|_| EncodeError::new(field, EncodeReason::OutOfRange))?;
+  out.extend(value.to_be_bytes());
+  Ok(())
+}
+
+fn put_price(
+  out: &mut Vec<u8>,
+  price: Price,
+  exchange: &Exchange,
+  field: &'static str,
+) -> Result<(), EncodeError> {
+  let scale = exchange.scale();
+  let units = price
+    .with_scale(scale)
+    .and_then(|p| i32::try_from(p.units()).ok())
+    .ok_or_else(|| {
+      let reason = if price.scale() > scale && price.with_scale(scale).is_none()
+      {
+        EncodeReason::Precision
+      } else {
+        EncodeReason::OutOfRange
+      };
+      EncodeError::new(field, reason)
+    })?;
+  out.extend(units.to_be_bytes());
+  Ok(())
+}
+
+fn put_timestamp(
+  out: &mut Vec<u8>,
+  at: DateTime<FixedOffset>,
+  field: &'static str,
+) -> Result<(), EncodeError> {
+  if at.timestamp_subsec_nanos() != 0 {
+    return Err(EncodeError::new(field, EncodeReason::Precision));
+  }
+  let secs = u32::try_from(at.timestamp())
+    .map_err(|_| EncodeError::new(field, EncodeReason::OutOfRange))?;
+  put_value(out, secs, field)
 }
@@ -1,7 +1,9 @@
 use serde::{Deserialize, Serialize};
 
+use crate::{DecodeError, DecodeReason, KiteTickerError};
+
 #[derive(
-  Debug, Clone, Deserialize, Serialize, Default, PartialEq, PartialOrd,
+  Debug, Clone, Deserialize, Serialize, Default, PartialEq, Eq, Hash, PartialOrd,
 )]
 #[serde(rename_all = "lowercase")]
 ///
@@ -15,13 +17,16 @@ pub enum Mode {
 }
 
 impl TryFrom<usize> for Mode {
-  type Error = String;
+  type Error = KiteTickerError;
   fn try_from(value: usize) -> Result<Self, Self::Error> {
     match value {
       8 => Ok(Self::LTP),
       44 => Ok(Self::Quote),
       184 => Ok(Self::Full),
-      _ => Err(format!("Invalid packet size: {}", value)),
+      _ => Err(KiteTickerError::Decode(DecodeError::new(
+        0,
+        DecodeReason::UnknownLength(value),
+      ))),
     }
   }
 }
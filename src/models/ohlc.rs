@@ -1,29 +1,43 @@
-use crate::Exchange;
+use crate::{DecodeError, EncodeError, Exchange, Price};
 
-use super::price;
+use super::{price, put_price};
 
 #[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 ///
 /// OHLC packet structure
 ///
 pub struct OHLC {
-  pub open: f64,
-  pub high: f64,
-  pub low: f64,
-  pub close: f64,
+  pub open: Price,
+  pub high: Price,
+  pub low: Price,
+  pub close: Price,
 }
 
 impl OHLC {
-  pub(crate) fn from(value: &[u8], exchange: &Exchange) -> Option<Self> {
-    if let Some(bs) = value.get(0..16) {
-      Some(OHLC {
-        open: price(&bs[0..=3], exchange).unwrap(),
-        high: price(&bs[4..=7], exchange).unwrap(),
-        low: price(&bs[8..=11], exchange).unwrap(),
-        close: price(&bs[12..=15], exchange).unwrap(),
-      })
-    } else {
-      None
-    }
+  pub(crate) fn from(
+    value: &[u8],
+    exchange: &Exchange,
+  ) -> Result<Self, DecodeError> {
+    let bs = value
+      .get(0..16)
+      .ok_or_else(|| DecodeError::truncated(0, 16, value))?;
+    Ok(OHLC {
+      open: price(&bs[0..=3], exchange).unwrap_or_default(),
+      high: price(&bs[4..=7], exchange).unwrap_or_default(),
+      low: price(&bs[8..=11], exchange).unwrap_or_default(),
+      close: price(&bs[12..=15], exchange).unwrap_or_default(),
+    })
+  }
+
+  pub(crate) fn write(
+    &self,
+    out: &mut Vec<u8>,
+    exchange: &Exchange,
+  ) -> Result<(), EncodeError> {
+    put_price(out, self.open, exchange, "open")?;
+    put_price(out, self.high, exchange, "high")?;
+    put_price(out, self.low, exchange, "low")?;
+    put_price(out, self.close, exchange, "close")
   }
 }
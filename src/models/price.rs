@@ -0,0 +1,336 @@
+use std::{
+  cmp::Ordering,
+  fmt,
+  hash::{Hash, Hasher},
+  ops::{Add, Neg, Sub},
+};
+
+/// Largest scale whose power of ten fits in an `i64`
+const MAX_SCALE: u32 = 18;
+
+#[derive(Clone, Copy, Default)]
+///
+/// Exact decimal price, `units` divided by 10 to the power of `scale`
+///
+/// Kite sends prices as integers scaled per segment, such as paise for
+/// equities, and they are kept that way so that they compare and subtract
+/// exactly. Prices of different scales are equal when their values are,
+/// `1573.15` equals `1573.150`.
+///
+pub struct Price {
+  units: i64,
+  scale: u32,
+}
+
+impl Price {
+  /// Price of `units / 10^scale`, panics if the scale is above 18
+  pub const fn new(units: i64, scale: u32) -> Self {
+    assert!(scale <= MAX_SCALE, "price scale above 18");
+    Self { units, scale }
+  }
+
+  /// Nearest price with the given number of decimals, `None` if the value
+  /// is not finite or does not fit
+  pub fn from_f64(value: f64, scale: u32) -> Option<Self> {
+    if scale > MAX_SCALE {
+      return None;
+    }
+    let units = (value * 10_f64.powi(scale as i32)).round();
+    // the bounds of i64 are not exact as f64, the range is checked against
+    // the first value that does not fit instead
+    (units.is_finite() && units.abs() < 9.223_372_036_854_776e18)
+      .then(|| Self::new(units as i64, scale))
+  }
+
+  /// The value multiplied by `10^scale`
+  pub fn units(&self) -> i64 {
+    self.units
+  }
+
+  /// Number of decimals
+  pub fn scale(&self) -> u32 {
+    self.scale
+  }
+
+  /// The same value with another number of decimals, `None` if decimals
+  /// would be lost or the units overflow
+  pub fn with_scale(&self, scale: u32) -> Option<Self> {
+    if scale > MAX_SCALE {
+      return None;
+    }
+    let units = if scale >= self.scale {
+      self.units.checked_mul(10_i64.pow(scale - self.scale))?
+    } else {
+      let divisor = 10_i64.pow(self.scale - scale);
+      (self.units % divisor == 0).then(|| self.units / divisor)?
+    };
+    Some(Self::new(units, scale))
+  }
+
+  /// Nearest `f64`, which converts back to the same price with
+  /// [`Price::from_f64`] at the same scale
+  pub fn to_f64(&self) -> f64 {
+    // both operands are exact so the division is correctly rounded
+    self.units as f64 / 10_f64.powi(self.scale as i32)
+  }
+
+  /// Difference of the prices, `None` if it does not fit
+  #[cfg(feature = "serde")]
+  pub(crate) fn checked_sub(self, rhs: Self) -> Option<Self> {
+    let (a, b, scale) = self.widen(&rhs);
+    Some(Self::new(i64::try_from(a - b).ok()?, scale))
+  }
+
+  /// Units of both prices at the larger of their scales
+  fn widen(&self, other: &Self) -> (i128, i128, u32) {
+    let scale = self.scale.max(other.scale);
+    let at = |p: &Self| p.units as i128 * 10_i128.pow(scale - p.scale);
+    (at(self), at(other), scale)
+  }
+
+  /// Units and scale without trailing zeros
+  fn normalized(&self) -> (i64, u32) {
+    let (mut units, mut scale) = (self.units, self.scale);
+    while scale > 0 && units % 10 == 0 {
+      units /= 10;
+      scale -= 1;
+    }
+    (units, scale)
+  }
+
+  fn from_wide(units: i128, scale: u32) -> Self {
+    Self::new(i64::try_from(units).expect("price overflow"), scale)
+  }
+}
+
+impl PartialEq for Price {
+  fn eq(&self, other: &Self) -> bool {
+    let (a, b, _) = self.widen(other);
+    a == b
+  }
+}
+
+impl Eq for Price {}
+
+impl PartialOrd for Price {
+  fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+    Some(self.cmp(other))
+  }
+}
+
+impl Ord for Price {
+  fn cmp(&self, other: &Self) -> Ordering {
+    let (a, b, _) = self.widen(other);
+    a.cmp(&b)
+  }
+}
+
+impl Hash for Price {
+  fn hash<H: Hasher>(&self, state: &mut H) {
+    self.normalized().hash(state);
+  }
+}
+
+impl Add for Price {
+  type Output = Price;
+
+  fn add(self, rhs: Self) -> Self::Output {
+    let (a, b, scale) = self.widen(&rhs);
+    Self::from_wide(a + b, scale)
+  }
+}
+
+impl Sub for Price {
+  type Output = Price;
+
+  fn sub(self, rhs: Self) -> Self::Output {
+    let (a, b, scale) = self.widen(&rhs);
+    Self::from_wide(a - b, scale)
+  }
+}
+
+impl Neg for Price {
+  type Output = Price;
+
+  fn neg(self) -> Self::Output {
+    Self::new(-self.units, self.scale)
+  }
+}
+
+impl From<Price> for f64 {
+  fn from(value: Price) -> Self {
+    value.to_f64()
+  }
+}
+
+impl fmt::Display for Price {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let sign = if self.units < 0 { "-" } else { "" };
+    let units = self.units.unsigned_abs();
+    if self.scale == 0 {
+      return write!(f, "{}{}", sign, units);
+    }
+    let divisor = 10_u64.pow(self.scale);
+    write!(
+      f,
+      "{}{}.{:0width$}",
+      sign,
+      units / divisor,
+      units % divisor,
+      width = self.scale as usize
+    )
+  }
+}
+
+impl fmt::Debug for Price {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    fmt::Display::fmt(self, f)
+  }
+}
+
+#[cfg(feature = "rust_decimal")]
+impl From<Price> for rust_decimal::Decimal {
+  fn from(value: Price) -> Self {
+    rust_decimal::Decimal::new(value.units, value.scale)
+  }
+}
+
+#[cfg(feature = "rust_decimal")]
+impl Price {
+  /// Same value as a decimal, `None` if it needs more than 18 decimals or
+  /// does not fit in 64 bits
+  pub fn from_decimal(value: rust_decimal::Decimal) -> Option<Self> {
+    let scale = value.scale();
+    let units = i64::try_from(value.mantissa()).ok()?;
+    (scale <= MAX_SCALE).then(|| Self::new(units, scale))
+  }
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for Price {
+  fn serialize<S: serde::Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+    s.serialize_f64(self.to_f64())
+  }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Price {
+  /// Read a JSON number, keeping the decimals it is written with
+  fn deserialize<D: serde::Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+    struct Visitor;
+
+    impl serde::de::Visitor<'_> for Visitor {
+      type Value = Price;
+
+      fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("a price")
+      }
+
+      fn visit_i64<E: serde::de::Error>(self, v: i64) -> Result<Price, E> {
+        Ok(Price::new(v, 0))
+      }
+
+      fn visit_u64<E: serde::de::Error>(self, v: u64) -> Result<Price, E> {
+        i64::try_from(v)
+          .map(|v| Price::new(v, 0))
+          .map_err(|_| E::custom("price out of range"))
+      }
+
+      fn visit_f64<E: serde::de::Error>(self, v: f64) -> Result<Price, E> {
+        // the shortest decimal that reads back as `v` is the one that was
+        // serialized
+        self.visit_str(&v.to_string())
+      }
+
+      fn visit_str<E: serde::de::Error>(self, v: &str) -> Result<Price, E> {
+        Price::parse(v).ok_or_else(|| E::custom("invalid price"))
+      }
+    }
+
+    d.deserialize_any(Visitor)
+  }
+}
+
+#[cfg(feature = "serde")]
+impl Price {
+  /// Price written as a plain decimal such as `-1573.15`
+  fn parse(input: &str) -> Option<Self> {
+    let (int, fraction) = input.split_once('.').unwrap_or((input, ""));
+    let digits = int.trim_start_matches('-');
+    let all_digits = |s: &str| s.bytes().all(|b| b.is_ascii_digit());
+    if digits.is_empty() || !all_digits(digits) || !all_digits(fraction) {
+      return None;
+    }
+    let scale = u32::try_from(fraction.len()).ok()?;
+    if scale > MAX_SCALE {
+      return None;
+    }
+    let units = format!("{}{}", int, fraction).parse().ok()?;
+    Some(Self::new(units, scale))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::collections::HashSet;
+
+  use super::Price;
+
+  #[test]
+  fn test_price() {
+    let ltp = Price::new(157315, 2);
+    assert_eq!(ltp.to_string(), "1573.15");
+    assert_eq!(ltp.to_f64(), 1573.15);
+    assert_eq!(Price::from_f64(ltp.to_f64(), 2), Some(ltp));
+    assert_eq!(ltp, Price::new(1573150, 3));
+    assert!(ltp < Price::new(1573151, 3));
+    assert_eq!(
+      HashSet::from([ltp, Price::new(1573150, 3)]).len(),
+      1,
+      "equal prices hash alike"
+    );
+
+    let change = Price::new(157370, 2) - Price::new(156780, 2);
+    assert_eq!(change, Price::new(590, 2));
+    assert_eq!(change.to_string(), "5.90");
+    assert_eq!((-change).to_string(), "-5.90");
+    assert_eq!(Price::new(-5, 2).to_string(), "-0.05");
+    assert_eq!(Price::new(7, 0).to_string(), "7");
+    assert_eq!((ltp + Price::new(1, 4)).to_string(), "1573.1501");
+
+    assert_eq!(ltp.with_scale(4), Some(Price::new(15731500, 4)));
+    assert_eq!(ltp.with_scale(1), None);
+    assert_eq!(Price::new(1500, 2).with_scale(0), Some(Price::new(15, 0)));
+    assert_eq!(Price::from_f64(f64::NAN, 2), None);
+    assert_eq!(Price::from_f64(1e30, 2), None);
+  }
+
+  #[cfg(feature = "rust_decimal")]
+  #[test]
+  fn test_decimal() {
+    let ltp = Price::new(157315, 2);
+    let decimal = rust_decimal::Decimal::from(ltp);
+    assert_eq!(decimal.to_string(), "1573.15");
+    assert_eq!(Price::from_decimal(decimal), Some(ltp));
+  }
+
+  #[cfg(feature = "serde")]
+  #[test]
+  fn test_serde() {
+    let ltp = Price::new(157315, 2);
+    assert_eq!(serde_json::to_string(&ltp).unwrap(), "1573.15");
+    let rate = Price::new(83_123_450, 6);
+    let json = serde_json::to_string(&rate).unwrap();
+    assert_eq!(json, "83.12345");
+    assert_eq!(serde_json::from_str::<Price>(&json).unwrap(), rate);
+    assert_eq!(
+      serde_json::from_str::<Price>("-7").unwrap(),
+      Price::new(-7, 0)
+    );
+    assert_eq!(
+      serde_json::from_str::<Price>("\"-0.05\"").unwrap(),
+      Price::new(-5, 2)
+    );
+    assert!(serde_json::from_str::<Price>("\"1.2.3\"").is_err());
+  }
+}
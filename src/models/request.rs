@@ -7,7 +7,7 @@ use crate::Mode;
 ///
 /// Websocket request actions
 ///
-enum RequestActions {
+pub(crate) enum RequestActions {
   Subscribe,
   Unsubscribe,
   Mode,
@@ -18,18 +18,56 @@ enum RequestActions {
 ///
 /// Websocket request data
 ///
-enum RequestData {
+pub(crate) enum RequestData {
   InstrumentTokens(Vec<u32>),
   InstrumentTokensWithMode(Mode, Vec<u32>),
 }
 
+#[cfg(feature = "tracing")]
+impl RequestActions {
+  pub(crate) fn as_str(&self) -> &'static str {
+    match self {
+      Self::Subscribe => "subscribe",
+      Self::Unsubscribe => "unsubscribe",
+      Self::Mode => "mode",
+    }
+  }
+}
+
+#[cfg(feature = "tracing")]
+impl RequestData {
+  pub(crate) fn token_count(&self) -> usize {
+    match self {
+      Self::InstrumentTokens(tokens) => tokens.len(),
+      Self::InstrumentTokensWithMode(_, tokens) => tokens.len(),
+    }
+  }
+
+  pub(crate) fn mode(&self) -> Option<&Mode> {
+    match self {
+      Self::InstrumentTokens(_) => None,
+      Self::InstrumentTokensWithMode(mode, _) => Some(mode),
+    }
+  }
+}
+
+#[cfg(feature = "testing")]
+impl RequestData {
+  pub(crate) fn tokens(self) -> Vec<u32> {
+    match self {
+      Self::InstrumentTokens(tokens) => tokens,
+      Self::InstrumentTokensWithMode(_, tokens) => tokens,
+    }
+  }
+}
+
 #[derive(Debug, Clone, Deserialize, Serialize)]
 ///
 /// Websocket request structure
 ///
 pub struct Request {
-  a: RequestActions,
-  v: RequestData,
+  pub(crate) a: RequestActions,
+  pub(crate) v: RequestData,
 }
 
 impl Request {
@@ -71,9 +109,10 @@ impl Request {
   }
 }
 
-impl ToString for Request {
-  fn to_string(&self) -> String {
-    serde_json::to_string(self)
-      .expect("failed to serialize TickerInput to JSON")
+impl std::fmt::Display for Request {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    let json = serde_json::to_string(self)
+      .expect("failed to serialize TickerInput to JSON");
+    f.write_str(&json)
   }
 }
@@ -1,13 +1,34 @@
-use std::time::Duration;
+use chrono::{DateTime, FixedOffset};
 
-use crate::{Depth, Exchange, Mode, OHLC};
+use crate::{
+  DecodeError, DecodeReason, Depth, EncodeError, EncodeReason, Exchange, Mode,
+  Price, OHLC,
+};
 
-use super::{price, value};
+use super::{
+  frame, packet_mode, price, put_price, put_timestamp, put_value, timestamp,
+  value,
+};
 
 #[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(
+  feature = "serde",
+  derive(serde::Serialize, serde::Deserialize),
+  serde(
+    from = "super::tick_json::TickJson",
+    into = "super::tick_json::TickJson"
+  )
+)]
 ///
 /// Quote packet structure
 ///
+/// Timestamps are in IST, the time zone of the exchanges.
+///
+/// With the `serde` feature it takes the JSON shape of pykiteconnect ticks,
+/// leaving out the fields the mode does not have. The net change is written
+/// as pykiteconnect's `change`, in percent of the close, and is taken again
+/// from the last and close prices when read.
+///
 pub struct Tick {
   pub mode: Mode,
   pub instrument_token: u32,
@@ -16,26 +37,118 @@ pub struct Tick {
   pub is_index: bool,
 
   pub last_traded_qty: Option<u32>,
-  pub avg_traded_price: Option<f64>,
-  pub last_price: Option<f64>,
+  pub avg_traded_price: Option<Price>,
+  pub last_price: Option<Price>,
   pub volume_traded: Option<u32>,
   pub total_buy_qty: Option<u32>,
   pub total_sell_qty: Option<u32>,
   pub ohlc: Option<OHLC>,
 
-  pub last_traded_timestamp: Option<Duration>,
+  pub last_traded_timestamp: Option<DateTime<FixedOffset>>,
   pub oi: Option<u32>,
   pub oi_day_high: Option<u32>,
   pub oi_day_low: Option<u32>,
-  pub exchange_timestamp: Option<Duration>,
+  pub exchange_timestamp: Option<DateTime<FixedOffset>>,
 
-  pub net_change: Option<f64>,
+  pub net_change: Option<Price>,
   pub depth: Option<Depth>,
 }
 
 impl Tick {
+  /// Encode as a quote packet of the tick's mode, without its length
+  /// prefix
+  ///
+  /// The layout is the one Kite sends, so decoding the packet gives back
+  /// the tick when it is one a packet decodes to. Unset fields are written
+  /// as zero, and the exchange, flags and net change of a tradable
+  /// instrument are left to be derived from the rest of the packet.
+  pub fn encode(&self) -> Result<Vec<u8>, EncodeError> {
+    let exchange = Exchange::from((self.instrument_token & 0xFF) as usize);
+    let ohlc = self.ohlc.clone().unwrap_or_default();
+    let mut packet = Vec::with_capacity(184);
+
+    // 0 - 4 bytes : instrument token
+    packet.extend(self.instrument_token.to_be_bytes());
+    // 4 - 8 bytes : ltp
+    let ltp = self.last_price.unwrap_or_default();
+    put_price(&mut packet, ltp, &exchange, "last_price")?;
+    if self.mode == Mode::LTP {
+      return Ok(packet);
+    }
+
+    if !exchange.is_tradable() {
+      // 8 - 24 bytes : ohlc
+      ohlc.write(&mut packet, &exchange)?;
+      // 24 - 28 bytes : Price change
+      let change = self.net_change.unwrap_or_default();
+      put_price(&mut packet, change, &exchange, "net_change")?;
+      if self.mode == Mode::Full {
+        // 28 - 32 bytes : exchange time
+        let ts = self.exchange_timestamp.unwrap_or_default();
+        put_timestamp(&mut packet, ts, "exchange_timestamp")?;
+      }
+      return Ok(packet);
+    }
+
+    // 8 - 12 bytes : last traded quantity
+    let ltq = self.last_traded_qty.unwrap_or_default();
+    put_value(&mut packet, ltq, "last_traded_qty")?;
+    // 12 - 16 bytes : avg traded price
+    let atp = self.avg_traded_price.unwrap_or_default();
+    put_price(&mut packet, atp, &exchange, "avg_traded_price")?;
+    // 16 - 20 bytes : volume traded today
+    let volume = self.volume_traded.unwrap_or_default();
+    put_value(&mut packet, volume, "volume_traded")?;
+    // 20 - 24 bytes : total buy quantity
+    let buy = self.total_buy_qty.unwrap_or_default();
+    put_value(&mut packet, buy, "total_buy_qty")?;
+    // 24 - 28 bytes : total sell quantity
+    let sell = self.total_sell_qty.unwrap_or_default();
+    put_value(&mut packet, sell, "total_sell_qty")?;
+    // 28 - 44 bytes : ohlc
+    ohlc.write(&mut packet, &exchange)?;
+    if self.mode == Mode::Quote {
+      return Ok(packet);
+    }
+
+    // 44 - 48 bytes : last traded timestamp
+    let ltt = self.last_traded_timestamp.unwrap_or_default();
+    put_timestamp(&mut packet, ltt, "last_traded_timestamp")?;
+    // 48 - 52 bytes : oi
+    put_value(&mut packet, self.oi.unwrap_or_default(), "oi")?;
+    // 52 - 56 bytes : oi day high
+    let oi_high = self.oi_day_high.unwrap_or_default();
+    put_value(&mut packet, oi_high, "oi_day_high")?;
+    // 56 - 60 bytes : oi day low
+    let oi_low = self.oi_day_low.unwrap_or_default();
+    put_value(&mut packet, oi_low, "oi_day_low")?;
+    // 60 - 64 bytes : exchange time
+    let ts = self.exchange_timestamp.unwrap_or_default();
+    put_timestamp(&mut packet, ts, "exchange_timestamp")?;
+    // 64 - 184 bytes : market depth
+    let depth = self.depth.clone().unwrap_or_default();
+    depth.write(&mut packet, &exchange)?;
+    Ok(packet)
+  }
+
+  /// Encode ticks as a binary frame, a packet count followed by length
+  /// prefixed packets
+  pub fn encode_frame<'a>(
+    ticks: impl IntoIterator<Item = &'a Tick>,
+  ) -> Result<Vec<u8>, EncodeError> {
+    let packets = ticks
+      .into_iter()
+      .map(Tick::encode)
+      .collect::<Result<Vec<_>, _>>()?;
+    if packets.len() > u16::MAX as usize {
+      return Err(EncodeError::new("packet count", EncodeReason::OutOfRange));
+    }
+    Ok(frame(&packets))
+  }
+
   fn set_instrument_token(&mut self, input: &[u8]) -> &mut Self {
-    self.instrument_token = value(&input[0..=3]).unwrap();
+    self.instrument_token =
+      u32::from_be_bytes([input[0], input[1], input[2], input[3]]);
     self.exchange = ((self.instrument_token & 0xFF) as usize).into();
     self
   }
@@ -47,8 +160,8 @@ impl Tick {
       .map(|o| o.close)
       .map(|close_price| {
         if let Some(last_price) = self.last_price {
-          if close_price == 0_f64 {
-            return None;
+          if close_price == Price::default() {
+            None
           } else {
             // Some(((last_price - close_price) * 100.0).div(close_price))
             Some(last_price - close_price)
@@ -62,18 +175,23 @@ impl Tick {
   }
 }
 
-impl From<&[u8]> for Tick {
-  fn from(input: &[u8]) -> Self {
+impl TryFrom<&[u8]> for Tick {
+  type Error = DecodeError;
+
+  /// Decode a single quote packet, without its length prefix
+  fn try_from(input: &[u8]) -> Result<Self, Self::Error> {
     let mut tick = Tick::default();
 
     let parse_ltp = |t: &mut Tick, i: &[u8]| {
+      if i.len() < 8 {
+        return Err(DecodeError::truncated(0, 8, i));
+      }
       // 0 - 4 bytes : instrument token
       t.set_instrument_token(i);
       // 4 - 8 bytes : ltp
-      if let Some(bs) = i.get(4..8) {
-        t.mode = Mode::LTP;
-        t.last_price = price(bs, &t.exchange);
-      }
+      t.mode = Mode::LTP;
+      t.last_price = price(&i[4..8], &t.exchange);
+      Ok(())
     };
 
     let parse_quote = |t: &mut Tick, i: &[u8], is_index: bool| {
@@ -81,7 +199,8 @@ impl From<&[u8]> for Tick {
         if let Some(bs) = i.get(8..28) {
           t.mode = Mode::Quote;
           // 8 - 24 bytes : ohlc
-          t.ohlc = OHLC::from(&bs[0..16], &t.exchange);
+          t.ohlc =
+            Some(OHLC::from(&bs[0..16], &t.exchange).map_err(|e| e.at(8))?);
           // 24 - 28 bytes : Price change
           // t.net_change = price(&bs[16..=19], &t.exchange);
           t.set_change();
@@ -100,9 +219,11 @@ impl From<&[u8]> for Tick {
           // 24 - 28 bytes : total sell quantity
           t.total_sell_qty = value(&bs[16..20]);
           // 28 - 44 bytes : ohlc
-          t.ohlc = OHLC::from(&bs[20..36], &t.exchange);
+          t.ohlc =
+            Some(OHLC::from(&bs[20..36], &t.exchange).map_err(|e| e.at(28))?);
         }
       }
+      Ok::<_, DecodeError>(())
     };
 
     let parse_full = |t: &mut Tick, i: &[u8], is_index: bool| {
@@ -110,17 +231,15 @@ impl From<&[u8]> for Tick {
         if let Some(bs) = i.get(28..32) {
           t.mode = Mode::Full;
           // 28 - 32 bytes : exchange time
-          t.exchange_timestamp =
-            value(bs).map(|x| Duration::from_secs(x.into()));
+          t.exchange_timestamp = timestamp(bs);
         }
       } else {
         if let Some(bs) = i.get(44..184) {
           t.mode = Mode::Full;
           t.set_change();
-          
+
           // 44 - 48 bytes : last traded timestamp
-          t.last_traded_timestamp =
-            value(&bs[0..4]).map(|x| Duration::from_secs(x.into()));
+          t.last_traded_timestamp = timestamp(&bs[0..4]);
 
           // 48 - 52 bytes : oi
           t.oi = value(&bs[4..8]);
@@ -129,29 +248,37 @@ impl From<&[u8]> for Tick {
           // 56 - 60 bytes : oi day low
           t.oi_day_low = value(&bs[12..16]);
           // 60 - 64 bytes : exchange time
-          t.exchange_timestamp =
-            value(&bs[16..20]).map(|x| Duration::from_secs(x.into()));
+          t.exchange_timestamp = timestamp(&bs[16..20]);
           // 64 - 184 bytes : market depth
-          t.depth = Depth::from(&bs[20..140], &t.exchange);
+          t.depth =
+            Some(Depth::from(&bs[20..140], &t.exchange).map_err(|e| e.at(64))?);
         }
       }
+      Ok::<_, DecodeError>(())
     };
 
-    parse_ltp(&mut tick, input);
+    parse_ltp(&mut tick, input)?;
+    let is_index = !tick.exchange.is_tradable();
+    if packet_mode(input.len(), is_index).is_none() {
+      return Err(DecodeError::new(
+        0,
+        DecodeReason::UnknownLength(input.len()),
+      ));
+    }
     if !tick.exchange.is_tradable() {
       tick.is_index = true;
       tick.is_tradable = false;
 
-      parse_quote(&mut tick, input, true);
-      parse_full(&mut tick, input, true);
+      parse_quote(&mut tick, input, true)?;
+      parse_full(&mut tick, input, true)?;
     } else {
       tick.is_index = false;
       tick.is_tradable = true;
 
-      parse_quote(&mut tick, input, false);
-      parse_full(&mut tick, input, false);
+      parse_quote(&mut tick, input, false)?;
+      parse_full(&mut tick, input, false)?;
     }
 
-    tick
+    Ok(tick)
   }
 }
@@ -0,0 +1,119 @@
+//! JSON shape of pykiteconnect ticks
+
+use chrono::{DateTime, FixedOffset};
+use serde::{Deserialize, Deserializer, Serialize};
+
+use crate::{Depth, Exchange, Mode, Price, Tick, OHLC};
+
+use super::ist;
+
+#[serde_with::skip_serializing_none]
+#[derive(Serialize, Deserialize)]
+///
+/// Tick as pykiteconnect builds it, where the exchange and flags follow
+/// from the instrument token and `change` is in percent of the close
+///
+pub(crate) struct TickJson {
+  #[serde(default)]
+  tradable: bool,
+  mode: Mode,
+  instrument_token: u32,
+  last_price: Option<Price>,
+  last_traded_quantity: Option<u32>,
+  average_traded_price: Option<Price>,
+  volume_traded: Option<u32>,
+  total_buy_quantity: Option<u32>,
+  total_sell_quantity: Option<u32>,
+  ohlc: Option<OHLC>,
+  change: Option<f64>,
+  #[serde(default, deserialize_with = "timestamp")]
+  last_trade_time: Option<DateTime<FixedOffset>>,
+  oi: Option<u32>,
+  oi_day_high: Option<u32>,
+  oi_day_low: Option<u32>,
+  #[serde(default, deserialize_with = "timestamp")]
+  exchange_timestamp: Option<DateTime<FixedOffset>>,
+  depth: Option<Depth>,
+}
+
+impl From<Tick> for TickJson {
+  fn from(tick: Tick) -> Self {
+    let close = tick.ohlc.as_ref().map(|o| o.close);
+    let change = tick
+      .net_change
+      .zip(close.filter(|close| *close != Price::default()))
+      .map(|(change, close)| change.to_f64() * 100.0 / close.to_f64());
+    Self {
+      tradable: tick.is_tradable,
+      mode: tick.mode,
+      instrument_token: tick.instrument_token,
+      last_price: tick.last_price,
+      last_traded_quantity: tick.last_traded_qty,
+      average_traded_price: tick.avg_traded_price,
+      volume_traded: tick.volume_traded,
+      total_buy_quantity: tick.total_buy_qty,
+      total_sell_quantity: tick.total_sell_qty,
+      ohlc: tick.ohlc,
+      change,
+      last_trade_time: tick.last_traded_timestamp,
+      oi: tick.oi,
+      oi_day_high: tick.oi_day_high,
+      oi_day_low: tick.oi_day_low,
+      exchange_timestamp: tick.exchange_timestamp,
+      depth: tick.depth,
+    }
+  }
+}
+
+impl From<TickJson> for Tick {
+  fn from(json: TickJson) -> Self {
+    let exchange = Exchange::from((json.instrument_token & 0xFF) as usize);
+    // the change is taken again from the prices, as the decoder does, since
+    // a percent can not give it back exactly
+    let net_change = json.change.and_then(|_| {
+      let close = json.ohlc.as_ref()?.close;
+      (close != Price::default())
+        .then(|| json.last_price?.checked_sub(close))
+        .flatten()
+    });
+    Self {
+      mode: json.mode,
+      instrument_token: json.instrument_token,
+      is_tradable: exchange.is_tradable(),
+      is_index: !exchange.is_tradable(),
+      exchange,
+      last_traded_qty: json.last_traded_quantity,
+      avg_traded_price: json.average_traded_price,
+      last_price: json.last_price,
+      volume_traded: json.volume_traded,
+      total_buy_qty: json.total_buy_quantity,
+      total_sell_qty: json.total_sell_quantity,
+      ohlc: json.ohlc,
+      last_traded_timestamp: json.last_trade_time,
+      oi: json.oi,
+      oi_day_high: json.oi_day_high,
+      oi_day_low: json.oi_day_low,
+      exchange_timestamp: json.exchange_timestamp,
+      net_change,
+      depth: json.depth,
+    }
+  }
+}
+
+/// RFC 3339 timestamp, or a local one such as `2021-07-05 10:41:27` taken
+/// to be in IST, which is how Python writes the datetimes of pykiteconnect
+fn timestamp<'de, D: Deserializer<'de>>(
+  d: D,
+) -> Result<Option<DateTime<FixedOffset>>, D::Error> {
+  let Some(at) = Option::<String>::deserialize(d)? else {
+    return Ok(None);
+  };
+  if let Ok(at) = DateTime::parse_from_rfc3339(&at) {
+    return Ok(Some(at));
+  }
+  chrono::NaiveDateTime::parse_from_str(&at, "%Y-%m-%d %H:%M:%S")
+    .ok()
+    .and_then(|at| at.and_local_timezone(ist::offset()).single())
+    .map(Some)
+    .ok_or_else(|| serde::de::Error::custom("invalid timestamp"))
+}
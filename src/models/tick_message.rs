@@ -1,19 +1,77 @@
+use chrono::{DateTime, FixedOffset, TimeDelta};
+
 use crate::Tick;
 
 #[derive(Debug, Clone, Default)]
+#[cfg_attr(
+  feature = "serde",
+  derive(serde::Serialize, serde::Deserialize),
+  serde(from = "TickMessageJson", into = "TickMessageJson")
+)]
 ///
 /// Parsed quote packet
 ///
+/// With the `serde` feature it is written as its tick with a `received_at`
+/// field, since the tick carries the instrument token too.
+///
 pub struct TickMessage {
   pub instrument_token: u32,
   pub content: Tick,
+  /// When the frame holding the packet was received, in IST like the
+  /// exchange timestamps
+  pub received_at: DateTime<FixedOffset>,
 }
 
 impl TickMessage {
-  pub(crate) fn new(instrument_token: u32, content: Tick) -> Self {
+  pub(crate) fn new(
+    instrument_token: u32,
+    content: Tick,
+    received_at: DateTime<FixedOffset>,
+  ) -> Self {
     Self {
       instrument_token,
       content,
+      received_at,
+    }
+  }
+
+  /// Time from the exchange timestamp to the frame being received, negative
+  /// when the clocks drift apart
+  pub fn latency(&self) -> Option<TimeDelta> {
+    self
+      .content
+      .exchange_timestamp
+      .map(|at| self.received_at - at)
+  }
+}
+
+impl From<TickMessage> for Tick {
+  fn from(value: TickMessage) -> Self {
+    value.content
+  }
+}
+
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+struct TickMessageJson {
+  #[serde(flatten)]
+  tick: Tick,
+  received_at: DateTime<FixedOffset>,
+}
+
+#[cfg(feature = "serde")]
+impl From<TickMessageJson> for TickMessage {
+  fn from(value: TickMessageJson) -> Self {
+    Self::new(value.tick.instrument_token, value.tick, value.received_at)
+  }
+}
+
+#[cfg(feature = "serde")]
+impl From<TickMessage> for TickMessageJson {
+  fn from(value: TickMessage) -> Self {
+    Self {
+      tick: value.content,
+      received_at: value.received_at,
     }
   }
 }
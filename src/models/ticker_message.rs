@@ -1,22 +1,122 @@
-use crate::{Order, TextMessage, TickMessage};
+use std::time::Duration;
+
+use chrono::{DateTime, FixedOffset, Utc};
+
+use crate::{KiteTickerError, Order, TextMessage, TickMessage};
 
 use super::text_message::TextMessageType;
 
 #[derive(Debug, Clone)]
+#[cfg_attr(
+  feature = "serde",
+  derive(serde::Serialize),
+  serde(tag = "type", content = "data", rename_all = "snake_case")
+)]
 ///
 /// Parsed message from websocket
 ///
+/// With the `serde` feature it serializes in the `type` and `data` shape of
+/// Kite's text messages, errors written as their message. It can not be
+/// deserialized since errors can not be rebuilt from their message.
+///
 pub enum TickerMessage {
   /// Quote packets for subscribed tokens
   Ticks(Vec<TickMessage>),
+  /// Binary frame left undecoded, to be read with a [`crate::FrameView`],
+  /// when the ticker is built with
+  /// [`crate::KiteTickerAsyncBuilder::raw_frames`], with the time it was
+  /// received in IST
+  Frame {
+    bytes: Vec<u8>,
+    received_at: DateTime<FixedOffset>,
+  },
+  /// 1 byte heartbeat the server sends roughly every second
+  Heartbeat,
   /// Error response
   Error(String),
   /// Order postback
-  OrderPostback(Result<Order, String>),
+  OrderPostback(
+    #[cfg_attr(feature = "serde", serde(serialize_with = "postback"))]
+    Result<Order, KiteTickerError>,
+  ),
   /// Messages and alerts from broker
   Message(serde_json::Value),
   /// Websocket closing frame
   ClosingMessage(serde_json::Value),
+  /// Connection established, first when the ticker starts and then after
+  /// every reconnect
+  Connected {
+    #[cfg_attr(
+      feature = "serde",
+      serde(serialize_with = "super::ist::serialize")
+    )]
+    at: DateTime<Utc>,
+    endpoint: String,
+  },
+  /// Connection closed or lost, data is stale until the next `Connected`
+  Disconnected { reason: DisconnectReason },
+  /// Nothing, not even a heartbeat, was received for `idle` so the
+  /// connection is considered dead
+  Stale { idle: Duration },
+  /// Connection dropped, the next reconnect `attempt` starts after `delay`,
+  /// with the error the previous attempt failed with
+  Reconnecting {
+    attempt: u32,
+    delay: Duration,
+    #[cfg_attr(feature = "serde", serde(serialize_with = "error_message"))]
+    last_error: Option<KiteTickerError>,
+  },
+  /// Connection re-established and the listed tokens were subscribed again
+  Resubscribed { tokens: Vec<u32> },
+  /// Reconnection abandoned after `attempts` failed tries, with the error
+  /// the last one failed with
+  GaveUp {
+    attempts: u32,
+    #[cfg_attr(feature = "serde", serde(serialize_with = "error_message"))]
+    last_error: Option<KiteTickerError>,
+  },
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(
+  feature = "serde",
+  derive(serde::Serialize),
+  serde(tag = "type", content = "data", rename_all = "snake_case")
+)]
+///
+/// Why the connection went down
+///
+pub enum DisconnectReason {
+  /// Closed on request, or because the ticker was dropped
+  Requested,
+  /// The server sent a close frame
+  Closed { code: u16, reason: String },
+  /// Nothing, not even a heartbeat, was received for `idle`
+  Stale { idle: Duration },
+  /// The connection failed or ended without a close frame, with the
+  /// transport error if there was one
+  Lost(
+    #[cfg_attr(feature = "serde", serde(serialize_with = "error_message"))]
+    Option<KiteTickerError>,
+  ),
+}
+
+impl DisconnectReason {
+  /// Whether reconnecting could get past the disconnect
+  pub fn is_retryable(&self) -> bool {
+    match self {
+      Self::Requested => false,
+      Self::Closed { code, reason } => KiteTickerError::Closed {
+        code: *code,
+        reason: reason.clone(),
+      }
+      .is_retryable(),
+      Self::Stale { .. } => true,
+      Self::Lost(error) => {
+        error.as_ref().is_none_or(KiteTickerError::is_retryable)
+      }
+    }
+  }
 }
 
 impl From<TextMessage> for TickerMessage {
@@ -24,10 +124,30 @@ impl From<TextMessage> for TickerMessage {
     let message_type: TextMessageType = value.message_type.into();
     match message_type {
       TextMessageType::Order => Self::OrderPostback(
-        serde_json::from_value(value.data).map_err(|e| e.to_string()),
+        serde_json::from_value(value.data).map_err(KiteTickerError::from),
       ),
       TextMessageType::Error => Self::Error(value.data.to_string()),
       TextMessageType::Message => Self::Message(value.data),
     }
   }
 }
+
+/// Order postback with the error written as its message
+#[cfg(feature = "serde")]
+fn postback<S: serde::Serializer>(
+  value: &Result<Order, KiteTickerError>,
+  s: S,
+) -> Result<S::Ok, S::Error> {
+  use serde::Serialize;
+  value.as_ref().map_err(ToString::to_string).serialize(s)
+}
+
+/// Error written as its message
+#[cfg(feature = "serde")]
+fn error_message<S: serde::Serializer>(
+  error: &Option<KiteTickerError>,
+  s: S,
+) -> Result<S::Ok, S::Error> {
+  use serde::Serialize;
+  error.as_ref().map(ToString::to_string).serialize(s)
+}
@@ -0,0 +1,289 @@
+use chrono::{DateTime, FixedOffset};
+
+use crate::{
+  DecodeError, DecodeReason, Depth, Exchange, Mode, Price, Tick, OHLC,
+};
+
+use super::{packet_length, packet_mode, price, timestamp, value};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+///
+/// Quote packet borrowed from a frame, decoding fields only when read
+///
+/// Fields follow the same rules as [`Tick`], so a field is `None` when the
+/// mode of the packet does not have it or when [`Tick`] would leave it
+/// unset. Packets whose length matches no mode are rejected like they are
+/// by [`Tick`].
+///
+pub struct TickView<'a> {
+  packet: &'a [u8],
+  offset: usize,
+}
+
+impl<'a> TickView<'a> {
+  /// View over a single quote packet, without its length prefix
+  pub fn new(packet: &'a [u8]) -> Result<Self, DecodeError> {
+    Self::at(packet, 0)
+  }
+
+  /// View over a packet found at `offset` in a frame
+  fn at(packet: &'a [u8], offset: usize) -> Result<Self, DecodeError> {
+    if packet.len() < 8 {
+      return Err(DecodeError::truncated(0, 8, packet).at(offset));
+    }
+    let view = Self { packet, offset };
+    if packet_mode(packet.len(), view.is_index()).is_none() {
+      let reason = DecodeReason::UnknownLength(packet.len());
+      return Err(DecodeError::new(offset, reason));
+    }
+    Ok(view)
+  }
+
+  /// Raw bytes of the packet
+  pub fn as_bytes(&self) -> &'a [u8] {
+    self.packet
+  }
+
+  /// Offset of the packet in its frame, 0 for a standalone packet
+  pub fn offset(&self) -> usize {
+    self.offset
+  }
+
+  pub fn instrument_token(&self) -> u32 {
+    u32::from_be_bytes([
+      self.packet[0],
+      self.packet[1],
+      self.packet[2],
+      self.packet[3],
+    ])
+  }
+
+  pub fn exchange(&self) -> Exchange {
+    ((self.instrument_token() & 0xFF) as usize).into()
+  }
+
+  pub fn is_index(&self) -> bool {
+    !self.exchange().is_tradable()
+  }
+
+  pub fn is_tradable(&self) -> bool {
+    self.exchange().is_tradable()
+  }
+
+  pub fn mode(&self) -> Mode {
+    // the length was checked when the view was made
+    packet_mode(self.packet.len(), self.is_index()).unwrap_or_default()
+  }
+
+  pub fn last_price(&self) -> Option<Price> {
+    self.price(4)
+  }
+
+  pub fn last_traded_qty(&self) -> Option<u32> {
+    self.tradable_quote().and_then(|bs| value(&bs[8..12]))
+  }
+
+  pub fn avg_traded_price(&self) -> Option<Price> {
+    self.tradable_quote().and_then(|_| self.price(12))
+  }
+
+  pub fn volume_traded(&self) -> Option<u32> {
+    self.tradable_quote().and_then(|bs| value(&bs[16..20]))
+  }
+
+  pub fn total_buy_qty(&self) -> Option<u32> {
+    self.tradable_quote().and_then(|bs| value(&bs[20..24]))
+  }
+
+  pub fn total_sell_qty(&self) -> Option<u32> {
+    self.tradable_quote().and_then(|bs| value(&bs[24..28]))
+  }
+
+  pub fn ohlc(&self) -> Option<OHLC> {
+    let start = if self.is_index() { 8 } else { 28 };
+    let bs = self.quote()?;
+    OHLC::from(&bs[start..start + 16], &self.exchange()).ok()
+  }
+
+  pub fn last_traded_timestamp(&self) -> Option<DateTime<FixedOffset>> {
+    self.tradable_full().and_then(|bs| timestamp(&bs[44..48]))
+  }
+
+  pub fn oi(&self) -> Option<u32> {
+    self.tradable_full().and_then(|bs| value(&bs[48..52]))
+  }
+
+  pub fn oi_day_high(&self) -> Option<u32> {
+    self.tradable_full().and_then(|bs| value(&bs[52..56]))
+  }
+
+  pub fn oi_day_low(&self) -> Option<u32> {
+    self.tradable_full().and_then(|bs| value(&bs[56..60]))
+  }
+
+  pub fn exchange_timestamp(&self) -> Option<DateTime<FixedOffset>> {
+    let start = if self.is_index() { 28 } else { 60 };
+    self.full().and_then(|bs| timestamp(&bs[start..start + 4]))
+  }
+
+  /// Change from the previous close, only in quote mode for indices and
+  /// in full mode for tradable instruments
+  pub fn net_change(&self) -> Option<Price> {
+    if self.is_index() {
+      self.quote()?;
+    } else {
+      self.full()?;
+    }
+    let close = self.ohlc()?.close;
+    if close == Price::default() {
+      return None;
+    }
+    self.last_price().map(|last_price| last_price - close)
+  }
+
+  /// Market depth of a full mode packet, decoded without allocating
+  pub fn depth(&self) -> Result<Option<Depth>, DecodeError> {
+    match self.tradable_full() {
+      Some(bs) => Depth::from(&bs[64..184], &self.exchange())
+        .map(Some)
+        .map_err(|e| e.at(self.offset + 64)),
+      None => Ok(None),
+    }
+  }
+
+  /// Decode every field into an owned [`Tick`]
+  pub fn to_tick(&self) -> Result<Tick, DecodeError> {
+    Tick::try_from(self.packet).map_err(|e| e.at(self.offset))
+  }
+
+  fn price(&self, start: usize) -> Option<Price> {
+    price(&self.packet[start..start + 4], &self.exchange())
+  }
+
+  /// Packet if it is long enough for quote mode
+  fn quote(&self) -> Option<&'a [u8]> {
+    let len = if self.is_index() { 28 } else { 44 };
+    (self.packet.len() >= len).then_some(self.packet)
+  }
+
+  /// Packet if it is long enough for full mode
+  fn full(&self) -> Option<&'a [u8]> {
+    let len = if self.is_index() { 32 } else { 184 };
+    (self.packet.len() >= len).then_some(self.packet)
+  }
+
+  fn tradable_quote(&self) -> Option<&'a [u8]> {
+    self.quote().filter(|_| self.is_tradable())
+  }
+
+  fn tradable_full(&self) -> Option<&'a [u8]> {
+    self.full().filter(|_| self.is_tradable())
+  }
+}
+
+impl TryFrom<TickView<'_>> for Tick {
+  type Error = DecodeError;
+
+  fn try_from(view: TickView<'_>) -> Result<Self, Self::Error> {
+    view.to_tick()
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+///
+/// Binary frame borrowed from the socket, made of a packet count followed
+/// by length prefixed quote packets
+///
+pub struct FrameView<'a> {
+  frame: &'a [u8],
+}
+
+impl<'a> FrameView<'a> {
+  pub fn new(frame: &'a [u8]) -> Self {
+    Self { frame }
+  }
+
+  /// Raw bytes of the frame
+  pub fn as_bytes(&self) -> &'a [u8] {
+    self.frame
+  }
+
+  /// Frames shorter than the packet count are heartbeats
+  pub fn is_heartbeat(&self) -> bool {
+    self.frame.len() < 2
+  }
+
+  /// Number of packets the frame claims to hold
+  pub fn packet_count(&self) -> usize {
+    packet_length(self.frame).unwrap_or_default()
+  }
+
+  /// Iterate over the packets without allocating
+  pub fn packets(&self) -> Packets<'a> {
+    Packets {
+      frame: self.frame,
+      remaining: self.packet_count(),
+      start: 2,
+    }
+  }
+}
+
+impl<'a> IntoIterator for FrameView<'a> {
+  type Item = Result<TickView<'a>, DecodeError>;
+  type IntoIter = Packets<'a>;
+
+  fn into_iter(self) -> Self::IntoIter {
+    self.packets()
+  }
+}
+
+#[derive(Debug, Clone)]
+///
+/// Iterator over the packets of a [`FrameView`]
+///
+/// A packet too short to hold a token and a price is reported and skipped.
+/// Iteration ends after a length prefix that runs past the frame, since
+/// the packets that follow can not be located.
+///
+pub struct Packets<'a> {
+  frame: &'a [u8],
+  remaining: usize,
+  start: usize,
+}
+
+impl<'a> Iterator for Packets<'a> {
+  type Item = Result<TickView<'a>, DecodeError>;
+
+  fn next(&mut self) -> Option<Self::Item> {
+    if self.remaining == 0 {
+      return None;
+    }
+    self.remaining -= 1;
+    let start = self.start;
+    // start - start + 2 : length of the packet
+    let Some(packet_len) = self.frame.get(start..).and_then(packet_length)
+    else {
+      self.remaining = 0;
+      return Some(Err(DecodeError::truncated(start, 2, self.frame)));
+    };
+    let next_start = start + 2 + packet_len;
+    match self.frame.get(start + 2..next_start) {
+      Some(packet) => {
+        self.start = next_start;
+        Some(TickView::at(packet, start + 2))
+      }
+      None => {
+        self.remaining = 0;
+        Some(Err(DecodeError::truncated(
+          start + 2,
+          packet_len,
+          self.frame,
+        )))
+      }
+    }
+  }
+
+  fn size_hint(&self) -> (usize, Option<usize>) {
+    (0, Some(self.remaining))
+  }
+}
@@ -0,0 +1,306 @@
+use std::collections::HashMap;
+
+use tokio::sync::mpsc;
+
+use crate::{
+  ConnectionState, KiteTickerAsyncBuilder, KiteTickerError, KiteTickerHandle,
+  Mode, TickerMessage,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+///
+/// Limits enforced by Kite on a single API key
+///
+pub struct PoolLimits {
+  /// Instruments a single connection may subscribe to
+  pub max_tokens_per_connection: usize,
+  /// Connections a single API key may open
+  pub max_connections: usize,
+}
+
+impl Default for PoolLimits {
+  fn default() -> Self {
+    Self {
+      max_tokens_per_connection: 3000,
+      max_connections: 3,
+    }
+  }
+}
+
+#[derive(Debug)]
+///
+/// Message received by one of the connections of a pool
+///
+pub struct ShardMessage {
+  pub shard: usize,
+  pub message: Result<TickerMessage, KiteTickerError>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+///
+/// Load and connection state of one connection of a pool
+///
+pub struct ShardStatus {
+  pub shard: usize,
+  pub tokens: usize,
+  pub state: ConnectionState,
+}
+
+#[derive(Debug)]
+struct Shard {
+  id: usize,
+  handle: KiteTickerHandle,
+}
+
+#[derive(Debug)]
+///
+/// Spreads subscriptions over several connections and merges their messages
+/// into a single stream
+///
+/// Connections are opened lazily as tokens are subscribed, new tokens go to
+/// the least loaded connection, and connections that are no longer needed
+/// after unsubscribing are drained into the others and closed.
+///
+pub struct KiteTickerPool {
+  builder: KiteTickerAsyncBuilder,
+  limits: PoolLimits,
+  shards: Vec<Shard>,
+  assignments: HashMap<u32, usize>,
+  next_shard: usize,
+  sender: mpsc::Sender<ShardMessage>,
+  messages: mpsc::Receiver<ShardMessage>,
+}
+
+impl KiteTickerPool {
+  /// Create a pool whose connections are opened with the given builder
+  pub fn new(builder: KiteTickerAsyncBuilder, limits: PoolLimits) -> Self {
+    let (sender, messages) = mpsc::channel(1024);
+    Self {
+      builder,
+      limits,
+      shards: vec![],
+      assignments: HashMap::new(),
+      next_shard: 0,
+      sender,
+      messages,
+    }
+  }
+
+  /// Get the list of subscribed instruments across all connections
+  pub fn get_subscribed(&self) -> Vec<u32> {
+    self.assignments.keys().copied().collect()
+  }
+
+  /// Load and connection state of every open connection
+  pub fn shards(&self) -> Vec<ShardStatus> {
+    self
+      .shards
+      .iter()
+      .map(|s| ShardStatus {
+        shard: s.id,
+        tokens: self.load(s.id),
+        state: s.handle.state(),
+      })
+      .collect()
+  }
+
+  fn load(&self, shard: usize) -> usize {
+    self.assignments.values().filter(|s| **s == shard).count()
+  }
+
+  fn handle(&self, shard: usize) -> &KiteTickerHandle {
+    &self.shards.iter().find(|s| s.id == shard).unwrap().handle
+  }
+
+  /// Group the given tokens, or every token if empty, by their connection
+  fn by_shard(&self, tokens: &[u32]) -> HashMap<usize, Vec<u32>> {
+    let mut groups: HashMap<usize, Vec<u32>> = HashMap::new();
+    let tokens = if tokens.is_empty() {
+      self.get_subscribed()
+    } else {
+      tokens.to_vec()
+    };
+    for token in tokens {
+      if let Some(shard) = self.assignments.get(&token) {
+        groups.entry(*shard).or_default().push(token);
+      }
+    }
+    groups
+  }
+
+  async fn open_shard(&mut self) -> Result<usize, KiteTickerError> {
+    let ticker = self.builder.clone().connect().await?;
+    let (handle, mut messages) = ticker.into_parts();
+    let id = self.next_shard;
+    self.next_shard += 1;
+
+    let sender = self.sender.clone();
+    tokio::spawn(async move {
+      while let Some(message) = messages.recv().await {
+        let message = ShardMessage { shard: id, message };
+        if sender.send(message).await.is_err() {
+          break;
+        }
+      }
+    });
+    self.shards.push(Shard { id, handle });
+    Ok(id)
+  }
+
+  /// Subscribe to tokens, opening connections as needed
+  ///
+  /// Tokens that are already subscribed are subscribed again on their
+  /// connection with the new mode.
+  pub async fn subscribe(
+    &mut self,
+    tokens: &[u32],
+    mode: Option<Mode>,
+  ) -> Result<(), KiteTickerError> {
+    let mut new_tokens = tokens
+      .iter()
+      .filter(|t| !self.assignments.contains_key(t))
+      .copied()
+      .collect::<Vec<_>>();
+    new_tokens.sort_unstable();
+    new_tokens.dedup();
+
+    let max = self.limits.max_tokens_per_connection;
+    let free = self
+      .shards
+      .iter()
+      .map(|s| max.saturating_sub(self.load(s.id)))
+      .sum::<usize>()
+      + self
+        .limits
+        .max_connections
+        .saturating_sub(self.shards.len())
+        * max;
+    if new_tokens.len() > free {
+      return Err(KiteTickerError::PoolFull {
+        requested: new_tokens.len(),
+        available: free,
+      });
+    }
+
+    let mut groups: HashMap<usize, Vec<u32>> = HashMap::new();
+    for token in tokens {
+      if let Some(shard) = self.assignments.get(token) {
+        groups.entry(*shard).or_default().push(*token);
+      }
+    }
+    let mut loads = self
+      .shards
+      .iter()
+      .map(|s| (s.id, self.load(s.id)))
+      .collect::<HashMap<_, _>>();
+    for token in new_tokens {
+      let least_loaded = loads
+        .iter()
+        .filter(|(_, load)| **load < max)
+        .min_by_key(|(id, load)| (**load, **id))
+        .map(|(id, _)| *id);
+      let shard = match least_loaded {
+        Some(shard) => shard,
+        None => self.open_shard().await?,
+      };
+      *loads.entry(shard).or_default() += 1;
+      groups.entry(shard).or_default().push(token);
+    }
+
+    for (shard, tokens) in groups {
+      self.handle(shard).subscribe(&tokens, mode.clone()).await?;
+      self
+        .assignments
+        .extend(tokens.into_iter().map(|t| (t, shard)));
+    }
+    Ok(())
+  }
+
+  /// Change the mode of subscribed tokens, if input is empty then the mode
+  /// of all subscribed tokens is changed
+  pub async fn set_mode(
+    &mut self,
+    tokens: &[u32],
+    mode: Mode,
+  ) -> Result<(), KiteTickerError> {
+    for (shard, tokens) in self.by_shard(tokens) {
+      self.handle(shard).set_mode(&tokens, mode.clone()).await?;
+    }
+    Ok(())
+  }
+
+  /// Unsubscribe tokens, if input is empty then all tokens are unsubscribed
+  ///
+  /// Connections left empty are closed, and if the remaining tokens fit on
+  /// fewer connections the least loaded ones are drained into the others.
+  pub async fn unsubscribe(
+    &mut self,
+    tokens: &[u32],
+  ) -> Result<(), KiteTickerError> {
+    for (shard, tokens) in self.by_shard(tokens) {
+      self.handle(shard).unsubscribe(&tokens).await?;
+      self.assignments.retain(|t, _| !tokens.contains(t));
+    }
+    self.rebalance().await
+  }
+
+  async fn rebalance(&mut self) -> Result<(), KiteTickerError> {
+    let max = self.limits.max_tokens_per_connection.max(1);
+    let needed = self.assignments.len().div_ceil(max);
+    while self.shards.len() > needed {
+      let source = self
+        .shards
+        .iter()
+        .map(|s| s.id)
+        .min_by_key(|id| (self.load(*id), usize::MAX - id))
+        .unwrap();
+      let subscriptions = self.handle(source).subscriptions();
+      let mut loads = self
+        .shards
+        .iter()
+        .filter(|s| s.id != source)
+        .map(|s| (s.id, self.load(s.id)))
+        .collect::<HashMap<_, _>>();
+
+      let mut moves: HashMap<(usize, Mode), Vec<u32>> = HashMap::new();
+      for (token, mode) in subscriptions {
+        let target = loads
+          .iter()
+          .filter(|(_, load)| **load < max)
+          .min_by_key(|(id, load)| (**load, **id))
+          .map(|(id, _)| *id)
+          .ok_or(KiteTickerError::PoolFull {
+            requested: 1,
+            available: 0,
+          })?;
+        *loads.get_mut(&target).unwrap() += 1;
+        moves.entry((target, mode)).or_default().push(token);
+      }
+      for ((target, mode), tokens) in moves {
+        self.handle(target).subscribe(&tokens, Some(mode)).await?;
+        self
+          .assignments
+          .extend(tokens.into_iter().map(|t| (t, target)));
+      }
+
+      let shard = self.shards.iter().position(|s| s.id == source).unwrap();
+      let shard = self.shards.remove(shard);
+      shard.handle.close().await?;
+    }
+    Ok(())
+  }
+
+  /// Get the next message from any connection, waiting if necessary
+  pub async fn next_message(&mut self) -> Option<ShardMessage> {
+    self.messages.recv().await
+  }
+
+  /// Close every connection
+  pub async fn close(&mut self) -> Result<(), KiteTickerError> {
+    self.assignments.clear();
+    for shard in self.shards.drain(..) {
+      shard.handle.close().await?;
+    }
+    Ok(())
+  }
+}
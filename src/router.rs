@@ -0,0 +1,336 @@
+use std::{
+  collections::{HashMap, HashSet},
+  pin::Pin,
+  sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc, Mutex,
+  },
+  task::{Context, Poll},
+};
+
+use futures_util::{Stream, StreamExt};
+use tokio::sync::{mpsc, watch};
+
+use crate::{
+  KiteTickerError, KiteTickerHandle, KiteTickerSubscriber, Mode, Tick,
+  TickerMessage,
+};
+
+#[derive(Debug)]
+struct Route {
+  tokens: Vec<u32>,
+  sender: mpsc::Sender<Tick>,
+  dropped: Arc<AtomicU64>,
+}
+
+/// Outcome of a subscribe, `None` while it is in flight
+type Subscribing = watch::Receiver<Option<Result<(), KiteTickerError>>>;
+
+#[derive(Debug, Default)]
+struct Routes {
+  routes: HashMap<u64, Route>,
+  /// Routes interested in each token, the token stays subscribed as long as
+  /// this is not empty
+  by_token: HashMap<u32, Vec<u64>>,
+  /// Tokens the router subscribed, the only ones it unsubscribes
+  owned: HashSet<u32>,
+  /// Tokens unsubscribed by the router that may still show as subscribed
+  /// on the handle, as the unsubscribe is sent without waiting
+  released: HashSet<u32>,
+  /// Subscribes in flight and the route that sent them, routes registered
+  /// meanwhile for the same tokens wait for them
+  subscribing: HashMap<u32, (u64, Subscribing)>,
+  next_id: u64,
+  /// The feed ended, new routes end right away
+  closed: bool,
+}
+
+impl Routes {
+  /// Remove a route and return the tokens nobody is interested in anymore
+  fn remove(&mut self, id: u64) -> Vec<u32> {
+    let Some(route) = self.routes.remove(&id) else {
+      return vec![];
+    };
+    let mut unused = vec![];
+    for token in route.tokens {
+      if self.subscribing.get(&token).is_some_and(|(i, _)| *i == id) {
+        self.subscribing.remove(&token);
+      }
+      if let Some(ids) = self.by_token.get_mut(&token) {
+        ids.retain(|i| *i != id);
+        if ids.is_empty() {
+          self.by_token.remove(&token);
+          if self.owned.remove(&token) {
+            self.released.insert(token);
+            unused.push(token);
+          }
+        }
+      }
+    }
+    unused
+  }
+
+  /// Settle the subscribe of `tokens` sent by a route, forgetting every
+  /// route's interest in them if it failed so that none of them is
+  /// unsubscribed later
+  fn subscribed(
+    &mut self,
+    id: u64,
+    tokens: &[u32],
+    result: &Result<(), KiteTickerError>,
+  ) {
+    for token in tokens {
+      if self.subscribing.get(token).is_some_and(|(i, _)| *i == id) {
+        self.subscribing.remove(token);
+      }
+      if result.is_err() {
+        self.by_token.remove(token);
+        self.owned.remove(token);
+      }
+    }
+  }
+}
+
+/// Wait for a subscribe sent by another route
+async fn wait_for(mut subscribing: Subscribing) -> Result<(), KiteTickerError> {
+  loop {
+    if let Some(result) = subscribing.borrow_and_update().clone() {
+      return result;
+    }
+    if subscribing.changed().await.is_err() {
+      // the route sending it was dropped before the subscribe completed
+      let result = subscribing.borrow().clone();
+      return result.unwrap_or(Err(KiteTickerError::NotConnected));
+    }
+  }
+}
+
+#[derive(Debug)]
+///
+/// Routes ticks to receivers registered for specific instruments
+///
+/// Tokens are subscribed when the first receiver interested in them is
+/// registered and unsubscribed when the last one is dropped. Tokens that
+/// are already subscribed through the handle when a receiver is registered
+/// are left alone, the router only unsubscribes tokens it subscribed itself.
+/// Every receiver has its own bounded buffer and ticks that do not fit are dropped for that
+/// receiver only. Messages other than ticks are discarded.
+///
+pub struct KiteTickerRouter {
+  handle: KiteTickerHandle,
+  routes: Arc<Mutex<Routes>>,
+  capacity: usize,
+}
+
+impl KiteTickerRouter {
+  /// Route the ticks of a subscriber, each receiver buffering up to
+  /// `capacity` ticks
+  pub fn new(subscriber: KiteTickerSubscriber, capacity: usize) -> Self {
+    Self::from_stream(subscriber.handle(), subscriber, capacity)
+  }
+
+  /// Route the ticks of any stream of messages, such as a
+  /// [`crate::BroadcastReceiver`], subscribing through the given handle
+  pub fn from_stream<S>(
+    handle: KiteTickerHandle,
+    messages: S,
+    capacity: usize,
+  ) -> Self
+  where
+    S: Stream<Item = Result<TickerMessage, KiteTickerError>>
+      + Send
+      + Unpin
+      + 'static,
+  {
+    let routes = Arc::new(Mutex::new(Routes::default()));
+    tokio::spawn(dispatch(messages, routes.clone()));
+    Self {
+      handle,
+      routes,
+      capacity: capacity.max(1),
+    }
+  }
+
+  /// Cloneable handle to control the connection from other tasks
+  pub fn handle(&self) -> KiteTickerHandle {
+    self.handle.clone()
+  }
+
+  /// Receive the ticks of the given tokens
+  ///
+  /// Tokens nobody else is interested in are subscribed in `mode`, or the
+  /// ticker's default mode, while tokens already subscribed keep their
+  /// current mode. If the subscribe fails, so does every route registered
+  /// for the same tokens while it was in flight.
+  pub async fn route(
+    &self,
+    tokens: &[u32],
+    mode: Option<Mode>,
+  ) -> Result<TickReceiver, KiteTickerError> {
+    let mut tokens = tokens.to_vec();
+    tokens.sort_unstable();
+    tokens.dedup();
+
+    let (sender, ticks) = mpsc::channel(self.capacity);
+    let dropped = Arc::new(AtomicU64::new(0));
+    let (outcome, subscribing) = watch::channel(None);
+    let (id, new_tokens, waiting) = {
+      let mut routes = self.routes.lock().unwrap();
+      if routes.closed {
+        return Err(KiteTickerError::TickerClosed);
+      }
+      let id = routes.next_id;
+      routes.next_id += 1;
+      let subscribed: HashSet<u32> =
+        self.handle.get_subscribed().into_iter().collect();
+      routes.released.retain(|token| subscribed.contains(token));
+      let mut new_tokens = vec![];
+      let mut waiting: Vec<Subscribing> = vec![];
+      for token in &tokens {
+        let direct =
+          subscribed.contains(token) && !routes.released.contains(token);
+        let ids = routes.by_token.entry(*token).or_default();
+        if ids.is_empty() && !direct {
+          new_tokens.push(*token);
+        }
+        ids.push(id);
+        if let Some((_, other)) = routes.subscribing.get(token) {
+          if !waiting.iter().any(|w| w.same_channel(other)) {
+            waiting.push(other.clone());
+          }
+        }
+      }
+      for token in &new_tokens {
+        routes.subscribing.insert(*token, (id, subscribing.clone()));
+        routes.owned.insert(*token);
+        routes.released.remove(token);
+      }
+      routes.routes.insert(
+        id,
+        Route {
+          tokens: tokens.clone(),
+          sender,
+          dropped: dropped.clone(),
+        },
+      );
+      (id, new_tokens, waiting)
+    };
+
+    // dropping the receiver, also when this future is, removes the route
+    let receiver = TickReceiver {
+      id,
+      tokens,
+      ticks,
+      dropped,
+      routes: self.routes.clone(),
+      handle: self.handle.clone(),
+    };
+    if !new_tokens.is_empty() {
+      let result = self.handle.subscribe(&new_tokens, mode).await;
+      self
+        .routes
+        .lock()
+        .unwrap()
+        .subscribed(id, &new_tokens, &result);
+      outcome.send_replace(Some(result.clone()));
+      result?;
+    }
+    for subscribing in waiting {
+      wait_for(subscribing).await?;
+    }
+    Ok(receiver)
+  }
+
+  /// Tokens with at least one receiver
+  pub fn routed_tokens(&self) -> Vec<u32> {
+    let routes = self.routes.lock().unwrap();
+    routes.by_token.keys().copied().collect()
+  }
+}
+
+async fn dispatch<S>(mut messages: S, routes: Arc<Mutex<Routes>>)
+where
+  S: Stream<Item = Result<TickerMessage, KiteTickerError>> + Unpin,
+{
+  while let Some(message) = messages.next().await {
+    // neither the router nor any receiver is left
+    if Arc::strong_count(&routes) == 1 {
+      break;
+    }
+    let Ok(TickerMessage::Ticks(ticks)) = message else {
+      continue;
+    };
+    let routes = routes.lock().unwrap();
+    for tick in ticks {
+      let Some(ids) = routes.by_token.get(&tick.instrument_token) else {
+        continue;
+      };
+      for route in ids.iter().filter_map(|id| routes.routes.get(id)) {
+        if route.sender.try_send(tick.content.clone()).is_err() {
+          route.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+      }
+    }
+  }
+
+  let mut routes = routes.lock().unwrap();
+  routes.closed = true;
+  routes.routes.clear();
+  routes.by_token.clear();
+  routes.subscribing.clear();
+}
+
+#[derive(Debug)]
+///
+/// Ticks of the instruments registered with [`KiteTickerRouter::route`]
+///
+/// Dropping the receiver unsubscribes the tokens no other receiver is
+/// interested in.
+///
+pub struct TickReceiver {
+  id: u64,
+  tokens: Vec<u32>,
+  ticks: mpsc::Receiver<Tick>,
+  dropped: Arc<AtomicU64>,
+  routes: Arc<Mutex<Routes>>,
+  handle: KiteTickerHandle,
+}
+
+impl TickReceiver {
+  /// Get the next tick, waiting if necessary. If the result is None then
+  /// the connection is terminated
+  pub async fn recv(&mut self) -> Option<Tick> {
+    self.ticks.recv().await
+  }
+
+  /// Tokens routed to this receiver
+  pub fn tokens(&self) -> &[u32] {
+    &self.tokens
+  }
+
+  /// Ticks dropped because the buffer was full
+  pub fn dropped(&self) -> u64 {
+    self.dropped.load(Ordering::Relaxed)
+  }
+}
+
+impl Stream for TickReceiver {
+  type Item = Tick;
+
+  fn poll_next(
+    mut self: Pin<&mut Self>,
+    cx: &mut Context<'_>,
+  ) -> Poll<Option<Self::Item>> {
+    self.ticks.poll_recv(cx)
+  }
+}
+
+impl Drop for TickReceiver {
+  fn drop(&mut self) {
+    let mut routes = self.routes.lock().unwrap();
+    let unused = routes.remove(self.id);
+    if !unused.is_empty() && !routes.closed {
+      self.handle.unsubscribe_detached(unused);
+    }
+  }
+}
@@ -0,0 +1,734 @@
+//! Local mock of the Kite ticker server for offline tests
+//!
+//! [`MockKiteServer`] listens on a loopback port and speaks the same protocol
+//! as `wss://ws.kite.trade`: it authenticates the handshake, tracks the
+//! `subscribe`, `unsubscribe` and `mode` requests of every connection, and
+//! streams binary packets, heartbeats and text messages that tests push into
+//! it. Connections can be closed or dropped on demand so reconnects can be
+//! exercised deterministically.
+//!
+//! ```no_run
+//! use kiteticker_async::testing::MockKiteServer;
+//! use kiteticker_async::{KiteTickerAsync, Mode, Price, Tick};
+//!
+//! # async fn run() -> Result<(), Box<dyn std::error::Error>> {
+//! let server = MockKiteServer::start("api_key", "access_token").await?;
+//! let ticker = KiteTickerAsync::builder()
+//!   .endpoint(server.url())
+//!   .credentials("api_key", "access_token")
+//!   .connect()
+//!   .await?;
+//! let mut subscriber = ticker.subscribe(&[408065], Some(Mode::LTP)).await?;
+//! server.wait_for_subscription(408065).await;
+//! server.publish(&[Tick {
+//!   instrument_token: 408065,
+//!   last_price: Some(Price::new(157315, 2)),
+//!   ..Default::default()
+//! }])?;
+//! let message = subscriber.next_message().await?;
+//! # Ok(())
+//! # }
+//! ```
+use std::{
+  collections::HashMap,
+  io,
+  net::SocketAddr,
+  sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc, Mutex,
+  },
+};
+
+use base64::Engine;
+use futures_util::{SinkExt, StreamExt};
+use serde_json::json;
+use tokio::{
+  io::{AsyncReadExt, AsyncWriteExt},
+  net::{TcpListener, TcpStream},
+  sync::{mpsc, Notify},
+  task::JoinHandle,
+};
+use tokio_tungstenite::{
+  accept_hdr_async,
+  tungstenite::{
+    handshake::server::{ErrorResponse, Request},
+    http::StatusCode,
+    protocol::{frame::coding::CloseCode, CloseFrame},
+    Message,
+  },
+};
+
+use crate::{
+  models::{RequestActions, RequestData},
+  EncodeError, Mode, Tick,
+};
+
+#[derive(Debug, Clone, PartialEq)]
+///
+/// Request received by the mock server from a client
+///
+pub enum MockRequest {
+  Subscribe(Vec<u32>),
+  Unsubscribe(Vec<u32>),
+  Mode(Mode, Vec<u32>),
+}
+
+impl From<crate::Request> for MockRequest {
+  fn from(value: crate::Request) -> Self {
+    match (value.a, value.v) {
+      (RequestActions::Mode, RequestData::InstrumentTokensWithMode(m, t)) => {
+        Self::Mode(m, t)
+      }
+      (RequestActions::Unsubscribe, data) => Self::Unsubscribe(data.tokens()),
+      (_, data) => Self::Subscribe(data.tokens()),
+    }
+  }
+}
+
+#[derive(Debug)]
+enum Outgoing {
+  Message(Message),
+  Pause(bool),
+  Drop,
+}
+
+#[derive(Debug)]
+struct Connection {
+  id: usize,
+  tx: mpsc::UnboundedSender<Outgoing>,
+  subscriptions: HashMap<u32, Mode>,
+}
+
+#[derive(Debug, Default)]
+struct State {
+  api_key: String,
+  access_token: String,
+  rejected_handshakes: usize,
+  rejection: StatusCode,
+  connections: Vec<Connection>,
+  requests: Vec<(usize, MockRequest)>,
+}
+
+#[derive(Debug, Default)]
+struct Shared {
+  state: Mutex<State>,
+  accepted: AtomicUsize,
+  pongs: AtomicUsize,
+  changed: Notify,
+}
+
+impl Shared {
+  fn update<T>(&self, f: impl FnOnce(&mut State) -> T) -> T {
+    let ret = f(&mut self.state.lock().unwrap());
+    self.changed.notify_waiters();
+    ret
+  }
+
+  /// Status to reject the handshake with, if any
+  fn authorize(&self, request: &Request) -> Option<StatusCode> {
+    let query = url::form_urlencoded::parse(
+      request.uri().query().unwrap_or_default().as_bytes(),
+    )
+    .into_owned()
+    .collect::<HashMap<_, _>>();
+    let mut state = self.state.lock().unwrap();
+    if state.rejected_handshakes > 0 {
+      state.rejected_handshakes -= 1;
+      return Some(state.rejection);
+    }
+    let authorized = query.get("api_key") == Some(&state.api_key)
+      && query.get("access_token") == Some(&state.access_token);
+    (!authorized).then_some(StatusCode::FORBIDDEN)
+  }
+}
+
+fn rejection(status: StatusCode) -> ErrorResponse {
+  let (message, error_type) = match status {
+    StatusCode::FORBIDDEN => {
+      ("Invalid `api_key` or `access_token`.", "TokenException")
+    }
+    _ => ("Service unavailable.", "NetworkException"),
+  };
+  let mut response = ErrorResponse::new(Some(
+    json!({
+      "status": "error",
+      "message": message,
+      "error_type": error_type
+    })
+    .to_string(),
+  ));
+  *response.status_mut() = status;
+  response
+}
+
+#[derive(Debug)]
+///
+/// Scriptable Kite ticker server bound to a loopback port
+///
+pub struct MockKiteServer {
+  addr: SocketAddr,
+  shared: Arc<Shared>,
+  accept_task: JoinHandle<()>,
+}
+
+impl MockKiteServer {
+  /// Start a server that accepts the given credentials
+  pub async fn start(api_key: &str, access_token: &str) -> io::Result<Self> {
+    let listener = TcpListener::bind(("127.0.0.1", 0)).await?;
+    let addr = listener.local_addr()?;
+    let shared = Arc::new(Shared::default());
+    shared.update(|state| {
+      state.api_key = api_key.to_string();
+      state.access_token = access_token.to_string();
+    });
+
+    let accept_shared = shared.clone();
+    let accept_task = tokio::spawn(async move {
+      while let Ok((stream, _)) = listener.accept().await {
+        tokio::spawn(serve(accept_shared.clone(), stream));
+      }
+    });
+
+    Ok(Self {
+      addr,
+      shared,
+      accept_task,
+    })
+  }
+
+  /// WebSocket URL to pass as the ticker endpoint
+  pub fn url(&self) -> String {
+    format!("ws://{}", self.addr)
+  }
+
+  /// Change the credentials accepted by future handshakes
+  pub fn set_credentials(&self, api_key: &str, access_token: &str) {
+    self.shared.update(|state| {
+      state.api_key = api_key.to_string();
+      state.access_token = access_token.to_string();
+    });
+  }
+
+  /// Reject the next `count` handshakes with HTTP 403, as Kite answers
+  /// invalid or expired credentials
+  pub fn reject_handshakes(&self, count: usize) {
+    self.shared.update(|state| {
+      state.rejected_handshakes = count;
+      state.rejection = StatusCode::FORBIDDEN;
+    });
+  }
+
+  /// Fail the next `count` handshakes with HTTP 503, a failure worth
+  /// retrying
+  pub fn fail_handshakes(&self, count: usize) {
+    self.shared.update(|state| {
+      state.rejected_handshakes = count;
+      state.rejection = StatusCode::SERVICE_UNAVAILABLE;
+    });
+  }
+
+  /// Number of WebSocket handshakes accepted so far
+  pub fn accepted_connections(&self) -> usize {
+    self.shared.accepted.load(Ordering::SeqCst)
+  }
+
+  /// Number of pongs received in reply to [`MockKiteServer::send_ping`]
+  pub fn pongs(&self) -> usize {
+    self.shared.pongs.load(Ordering::SeqCst)
+  }
+
+  /// Number of currently open connections
+  pub fn open_connections(&self) -> usize {
+    self.shared.state.lock().unwrap().connections.len()
+  }
+
+  /// Subscriptions of the most recent open connection
+  pub fn subscriptions(&self) -> HashMap<u32, Mode> {
+    let state = self.shared.state.lock().unwrap();
+    state
+      .connections
+      .last()
+      .map(|c| c.subscriptions.clone())
+      .unwrap_or_default()
+  }
+
+  /// Subscriptions of every open connection, oldest first
+  pub fn all_subscriptions(&self) -> Vec<HashMap<u32, Mode>> {
+    let state = self.shared.state.lock().unwrap();
+    state
+      .connections
+      .iter()
+      .map(|c| c.subscriptions.clone())
+      .collect()
+  }
+
+  /// Every request received so far, across all connections
+  pub fn requests(&self) -> Vec<MockRequest> {
+    let state = self.shared.state.lock().unwrap();
+    state.requests.iter().map(|(_, r)| r.clone()).collect()
+  }
+
+  /// Wait until `count` handshakes have been accepted in total
+  pub async fn wait_for_connections(&self, count: usize) {
+    self.wait(|_| self.accepted_connections() >= count).await
+  }
+
+  /// Wait until the most recent connection is subscribed to `token`
+  pub async fn wait_for_subscription(&self, token: u32) {
+    self
+      .wait(|state| {
+        state
+          .connections
+          .last()
+          .map(|c| c.subscriptions.contains_key(&token))
+          .unwrap_or(false)
+      })
+      .await
+  }
+
+  /// Wait until the most recent connection is no longer subscribed to `token`
+  pub async fn wait_for_unsubscription(&self, token: u32) {
+    self
+      .wait(|state| {
+        state
+          .connections
+          .last()
+          .map(|c| !c.subscriptions.contains_key(&token))
+          .unwrap_or(true)
+      })
+      .await
+  }
+
+  /// Wait until the most recent connection streams `token` in `mode`
+  pub async fn wait_for_mode(&self, token: u32, mode: Mode) {
+    self
+      .wait(|state| {
+        state
+          .connections
+          .last()
+          .and_then(|c| c.subscriptions.get(&token))
+          .map(|m| *m == mode)
+          .unwrap_or(false)
+      })
+      .await
+  }
+
+  /// Wait until exactly `connections` connections are open and subscribed to
+  /// `count` tokens in total
+  pub async fn wait_for_spread(&self, connections: usize, count: usize) {
+    self
+      .wait(|state| {
+        state.connections.len() == connections
+          && state
+            .connections
+            .iter()
+            .map(|c| c.subscriptions.len())
+            .sum::<usize>()
+            == count
+      })
+      .await
+  }
+
+  /// Wait until `count` pongs have been received in total
+  pub async fn wait_for_pongs(&self, count: usize) {
+    self.wait(|_| self.pongs() >= count).await
+  }
+
+  /// Wait until `count` requests have been received in total
+  pub async fn wait_for_requests(&self, count: usize) {
+    self.wait(|state| state.requests.len() >= count).await
+  }
+
+  async fn wait(&self, done: impl Fn(&State) -> bool) {
+    loop {
+      let changed = self.shared.changed.notified();
+      if done(&self.shared.state.lock().unwrap()) {
+        return;
+      }
+      changed.await;
+    }
+  }
+
+  /// Send ticks to every connection subscribed to them, in the subscribed
+  /// mode
+  ///
+  /// Nothing is sent if a tick does not fit in a packet of a subscribed mode.
+  pub fn publish(&self, ticks: &[Tick]) -> Result<(), EncodeError> {
+    let state = self.shared.state.lock().unwrap();
+    let mut frames = vec![];
+    for conn in &state.connections {
+      let packets = ticks
+        .iter()
+        .filter_map(|t| {
+          conn
+            .subscriptions
+            .get(&t.instrument_token)
+            .map(|mode| encode_tick(t, mode))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+      if !packets.is_empty() {
+        frames.push((&conn.tx, encode_frame(&packets)));
+      }
+    }
+    for (tx, frame) in frames {
+      let _ = tx.send(Outgoing::Message(Message::Binary(frame)));
+    }
+    Ok(())
+  }
+
+  /// Send a raw binary frame to every connection
+  pub fn send_binary(&self, frame: Vec<u8>) {
+    self.broadcast(Message::Binary(frame));
+  }
+
+  /// Send a WebSocket ping to every connection
+  pub fn send_ping(&self) {
+    self.broadcast(Message::Ping(vec![]));
+  }
+
+  /// Send the 1 byte heartbeat to every connection
+  pub fn heartbeat(&self) {
+    self.send_binary(vec![0]);
+  }
+
+  /// Send an order postback to every connection
+  pub fn send_order_postback(&self, order: serde_json::Value) {
+    self.send_text("order", order);
+  }
+
+  /// Send an error text message to every connection
+  pub fn send_error(&self, message: &str) {
+    self.send_text("error", json!(message));
+  }
+
+  /// Send a broker message to every connection
+  pub fn send_message(&self, message: serde_json::Value) {
+    self.send_text("message", message);
+  }
+
+  fn send_text(&self, message_type: &str, data: serde_json::Value) {
+    self.broadcast(Message::Text(
+      json!({ "type": message_type, "data": data }).to_string(),
+    ));
+  }
+
+  /// Start the closing handshake on every connection
+  pub fn close_connections(&self, code: u16, reason: &str) {
+    self.broadcast(Message::Close(Some(CloseFrame {
+      code: CloseCode::from(code),
+      reason: reason.to_string().into(),
+    })));
+  }
+
+  /// Stop or resume reading from every connection, pings stay unanswered
+  /// and requests unseen while reading is paused
+  pub fn pause_reading(&self, paused: bool) {
+    let state = self.shared.state.lock().unwrap();
+    for conn in &state.connections {
+      let _ = conn.tx.send(Outgoing::Pause(paused));
+    }
+  }
+
+  /// Drop every connection without a closing handshake
+  pub fn drop_connections(&self) {
+    let state = self.shared.state.lock().unwrap();
+    for conn in &state.connections {
+      let _ = conn.tx.send(Outgoing::Drop);
+    }
+  }
+
+  fn broadcast(&self, message: Message) {
+    let state = self.shared.state.lock().unwrap();
+    for conn in &state.connections {
+      let _ = conn.tx.send(Outgoing::Message(message.clone()));
+    }
+  }
+}
+
+impl Drop for MockKiteServer {
+  fn drop(&mut self) {
+    self.accept_task.abort();
+    self.drop_connections();
+  }
+}
+
+#[allow(clippy::result_large_err)]
+async fn serve(shared: Arc<Shared>, stream: TcpStream) {
+  let authorize = |req: &Request, res| match shared.authorize(req) {
+    None => Ok(res),
+    Some(status) => Err(rejection(status)),
+  };
+  let ws_stream = match accept_hdr_async(stream, authorize).await {
+    Ok(ws_stream) => ws_stream,
+    Err(_) => return,
+  };
+  let id = shared.accepted.fetch_add(1, Ordering::SeqCst);
+  let (tx, mut rx) = mpsc::unbounded_channel();
+  shared.update(|state| {
+    state.connections.push(Connection {
+      id,
+      tx,
+      subscriptions: HashMap::new(),
+    })
+  });
+
+  let (mut sink, mut stream) = ws_stream.split();
+  let mut paused = false;
+  loop {
+    tokio::select! {
+      outgoing = rx.recv() => match outgoing {
+        Some(Outgoing::Message(message)) => {
+          if sink.send(message).await.is_err() {
+            break;
+          }
+        }
+        Some(Outgoing::Pause(pause)) => paused = pause,
+        Some(Outgoing::Drop) | None => break,
+      },
+      incoming = stream.next(), if !paused => match incoming {
+        Some(Ok(Message::Text(text))) => {
+          if let Ok(request) = serde_json::from_str::<crate::Request>(&text) {
+            shared.update(|state| handle_request(state, id, request.into()));
+          }
+        }
+        Some(Ok(Message::Pong(_))) => {
+          shared.pongs.fetch_add(1, Ordering::SeqCst);
+          shared.changed.notify_waiters();
+        }
+        Some(Ok(_)) => {}
+        Some(Err(_)) | None => break,
+      },
+    }
+  }
+
+  shared.update(|state| state.connections.retain(|c| c.id != id));
+}
+
+fn handle_request(state: &mut State, id: usize, request: MockRequest) {
+  if let Some(conn) = state.connections.iter_mut().find(|c| c.id == id) {
+    match &request {
+      MockRequest::Subscribe(tokens) => {
+        for token in tokens {
+          conn.subscriptions.entry(*token).or_default();
+        }
+      }
+      MockRequest::Unsubscribe(tokens) => {
+        conn.subscriptions.retain(|t, _| !tokens.contains(t));
+      }
+      MockRequest::Mode(mode, tokens) => {
+        for token in tokens {
+          if let Some(m) = conn.subscriptions.get_mut(token) {
+            *m = mode.clone();
+          }
+        }
+      }
+    }
+  }
+  state.requests.push((id, request));
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ProxyKind {
+  Http,
+  Socks5,
+}
+
+#[derive(Debug)]
+///
+/// HTTP `CONNECT` or SOCKS5 proxy bound to a loopback port
+///
+/// The proxy opens tunnels to any target, optionally requiring credentials,
+/// and records the targets it was asked to connect to.
+///
+pub struct MockProxy {
+  addr: SocketAddr,
+  targets: Arc<Mutex<Vec<String>>>,
+  accept_task: JoinHandle<()>,
+}
+
+impl MockProxy {
+  /// Start an HTTP proxy, requiring basic auth with `auth` if given
+  pub async fn start_http(auth: Option<(&str, &str)>) -> io::Result<Self> {
+    Self::start(ProxyKind::Http, auth).await
+  }
+
+  /// Start a SOCKS5 proxy, requiring username/password authentication with
+  /// `auth` if given
+  pub async fn start_socks5(auth: Option<(&str, &str)>) -> io::Result<Self> {
+    Self::start(ProxyKind::Socks5, auth).await
+  }
+
+  async fn start(
+    kind: ProxyKind,
+    auth: Option<(&str, &str)>,
+  ) -> io::Result<Self> {
+    let listener = TcpListener::bind(("127.0.0.1", 0)).await?;
+    let addr = listener.local_addr()?;
+    let targets = Arc::new(Mutex::new(vec![]));
+    let auth = auth.map(|(u, p)| (u.to_string(), p.to_string()));
+
+    let accept_targets = targets.clone();
+    let accept_task = tokio::spawn(async move {
+      while let Ok((stream, _)) = listener.accept().await {
+        let targets = accept_targets.clone();
+        let auth = auth.clone();
+        tokio::spawn(async move {
+          let _ = relay(kind, auth, targets, stream).await;
+        });
+      }
+    });
+
+    Ok(Self {
+      addr,
+      targets,
+      accept_task,
+    })
+  }
+
+  /// Port the proxy listens on
+  pub fn port(&self) -> u16 {
+    self.addr.port()
+  }
+
+  /// `host:port` targets of the tunnels opened so far
+  pub fn targets(&self) -> Vec<String> {
+    self.targets.lock().unwrap().clone()
+  }
+}
+
+impl Drop for MockProxy {
+  fn drop(&mut self) {
+    self.accept_task.abort();
+  }
+}
+
+async fn relay(
+  kind: ProxyKind,
+  auth: Option<(String, String)>,
+  targets: Arc<Mutex<Vec<String>>>,
+  mut client: TcpStream,
+) -> io::Result<()> {
+  let target = match kind {
+    ProxyKind::Http => accept_connect(&mut client, auth.as_ref()).await?,
+    ProxyKind::Socks5 => accept_socks5(&mut client, auth.as_ref()).await?,
+  };
+  let Some(target) = target else {
+    return Ok(());
+  };
+  let mut upstream = TcpStream::connect(target.as_str()).await?;
+  targets.lock().unwrap().push(target);
+  match kind {
+    ProxyKind::Http => {
+      client
+        .write_all(b"HTTP/1.1 200 Connection established\r\n\r\n")
+        .await?
+    }
+    ProxyKind::Socks5 => {
+      client.write_all(&[5, 0, 0, 1, 0, 0, 0, 0, 0, 0]).await?
+    }
+  }
+  tokio::io::copy_bidirectional(&mut client, &mut upstream).await?;
+  Ok(())
+}
+
+/// Read a `CONNECT` request, returning its target unless authentication
+/// failed
+async fn accept_connect(
+  client: &mut TcpStream,
+  auth: Option<&(String, String)>,
+) -> io::Result<Option<String>> {
+  let mut head = vec![];
+  while !head.ends_with(b"\r\n\r\n") {
+    head.push(client.read_u8().await?);
+  }
+  let head = String::from_utf8_lossy(&head);
+  let mut lines = head.lines();
+  let target = lines
+    .next()
+    .and_then(|line| line.strip_prefix("CONNECT "))
+    .and_then(|line| line.split_whitespace().next())
+    .unwrap_or_default()
+    .to_string();
+  if let Some((username, password)) = auth {
+    let expected = format!(
+      "proxy-authorization: basic {}",
+      base64::engine::general_purpose::STANDARD
+        .encode(format!("{}:{}", username, password))
+    );
+    if !lines.any(|line| line.to_lowercase() == expected.to_lowercase()) {
+      client
+        .write_all(b"HTTP/1.1 407 Proxy Authentication Required\r\n\r\n")
+        .await?;
+      return Ok(None);
+    }
+  }
+  Ok(Some(target))
+}
+
+/// Run the SOCKS5 handshake, returning the target unless authentication
+/// failed
+async fn accept_socks5(
+  client: &mut TcpStream,
+  auth: Option<&(String, String)>,
+) -> io::Result<Option<String>> {
+  let mut greeting = [0; 2];
+  client.read_exact(&mut greeting).await?;
+  let mut methods = vec![0; greeting[1] as usize];
+  client.read_exact(&mut methods).await?;
+  let method = if auth.is_some() { 2 } else { 0 };
+  if !methods.contains(&method) {
+    client.write_all(&[5, 0xFF]).await?;
+    return Ok(None);
+  }
+  client.write_all(&[5, method]).await?;
+
+  if let Some((username, password)) = auth {
+    let _version = client.read_u8().await?;
+    let given = (read_field(client).await?, read_field(client).await?);
+    if given != (username.clone(), password.clone()) {
+      client.write_all(&[1, 1]).await?;
+      return Ok(None);
+    }
+    client.write_all(&[1, 0]).await?;
+  }
+
+  let mut request = [0; 4];
+  client.read_exact(&mut request).await?;
+  let host = match request[3] {
+    1 => {
+      let mut ip = [0; 4];
+      client.read_exact(&mut ip).await?;
+      std::net::Ipv4Addr::from(ip).to_string()
+    }
+    3 => {
+      let mut host = vec![0; client.read_u8().await? as usize];
+      client.read_exact(&mut host).await?;
+      String::from_utf8_lossy(&host).into_owned()
+    }
+    _ => return Ok(None),
+  };
+  let port = client.read_u16().await?;
+  Ok(Some(format!("{}:{}", host, port)))
+}
+
+/// Read a length-prefixed SOCKS5 field
+async fn read_field(client: &mut TcpStream) -> io::Result<String> {
+  let mut field = vec![0; client.read_u8().await? as usize];
+  client.read_exact(&mut field).await?;
+  Ok(String::from_utf8_lossy(&field).into_owned())
+}
+
+/// Frame packets the way Kite does: a packet count followed by
+/// length-prefixed packets
+pub fn encode_frame(packets: &[Vec<u8>]) -> Vec<u8> {
+  crate::models::frame(packets)
+}
+
+/// Encode a tick as a binary packet of the given mode
+pub fn encode_tick(tick: &Tick, mode: &Mode) -> Result<Vec<u8>, EncodeError> {
+  let tick = Tick {
+    mode: mode.clone(),
+    ..tick.clone()
+  };
+  tick.encode()
+}
@@ -1,25 +1,162 @@
-use crate::models::{
-  packet_length, Mode, Request, TextMessage, Tick, TickMessage, TickerMessage,
+use crate::builder::{KiteTickerAsyncBuilder, TickerConfig};
+use crate::connection::{Command, Reply, Shared, Worker};
+use crate::models::{Mode, Order, TickMessage, TickerMessage};
+use crate::{Credentials, KiteTickerError, Metrics};
+use futures_util::{future, Stream, StreamExt};
+use std::{
+  collections::{hash_map::RandomState, HashMap},
+  hash::{BuildHasher, Hasher},
+  pin::Pin,
+  sync::Arc,
+  task::{Context, Poll},
+  time::Duration,
 };
-use futures_util::{stream::iter, SinkExt, StreamExt};
-use serde_json::json;
-use std::{collections::HashMap, sync::Arc};
 use tokio::net::TcpStream;
-use tokio::sync::Mutex;
-use tokio_tungstenite::{
-  connect_async, tungstenite::Message, MaybeTlsStream, WebSocketStream,
-};
+use tokio::sync::{mpsc, oneshot};
+use tokio_tungstenite::{MaybeTlsStream, WebSocketStream};
+use tokio_util::sync::CancellationToken;
 
-#[derive(Debug, Clone)]
+pub(crate) type WsStream = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+#[derive(Debug, Clone, PartialEq)]
+///
+/// Exponential backoff policy used to reconnect a dropped connection
+///
+/// Attempts failing with an error retrying can not get past, such as a
+/// rejected access token, end the reconnect right away unless a
+/// [`crate::CredentialsProvider`] can supply fresh credentials.
+///
+pub struct ReconnectPolicy {
+  /// Reconnect automatically when the connection drops
+  pub enabled: bool,
+  /// Maximum consecutive attempts before giving up, `None` retries forever
+  pub max_retries: Option<u32>,
+  /// Delay before the first attempt
+  pub initial_delay: Duration,
+  /// Upper bound for the delay between attempts
+  pub max_delay: Duration,
+  /// Factor applied to the delay after every failed attempt
+  pub multiplier: f64,
+  /// Fraction of the delay, between 0.0 and 1.0, that is randomised
+  pub jitter: f64,
+}
+
+impl Default for ReconnectPolicy {
+  fn default() -> Self {
+    Self {
+      enabled: true,
+      max_retries: Some(50),
+      initial_delay: Duration::from_millis(500),
+      max_delay: Duration::from_secs(60),
+      multiplier: 2.0,
+      jitter: 0.2,
+    }
+  }
+}
+
+impl ReconnectPolicy {
+  /// A policy that never reconnects
+  pub fn disabled() -> Self {
+    Self {
+      enabled: false,
+      ..Default::default()
+    }
+  }
+
+  /// Delay to wait before the given attempt, starting from 1
+  pub fn delay_for(&self, attempt: u32) -> Duration {
+    let exponent = i32::try_from(attempt.saturating_sub(1)).unwrap_or(i32::MAX);
+    let exp = self.multiplier.max(1.0).powi(exponent);
+    let delay = (self.initial_delay.as_secs_f64() * exp)
+      .min(self.max_delay.as_secs_f64());
+    // NaN is no jitter at all rather than a panic in the worker
+    let jitter = if self.jitter.is_nan() {
+      0.0
+    } else {
+      self.jitter.clamp(0.0, 1.0)
+    };
+    let random =
+      RandomState::new().build_hasher().finish() as f64 / u64::MAX as f64;
+    // the bound itself may not survive the round trip through f64, such as
+    // `Duration::MAX`, so anything out of range falls back to it
+    Duration::try_from_secs_f64(delay * (1.0 - jitter * random))
+      .map_or(self.max_delay, |delay| delay.min(self.max_delay))
+  }
+
+  pub(crate) fn exhausted(&self, attempt: u32) -> bool {
+    self.max_retries.map(|max| attempt > max).unwrap_or(false)
+  }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+///
+/// State of the connection owned by a ticker
+///
+pub enum ConnectionState {
+  /// Connected and streaming
+  #[default]
+  Connected,
+  /// Connection lost, waiting for the given reconnect attempt
+  Reconnecting { attempt: u32 },
+  /// Closed on request or after giving up reconnecting
+  Closed,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+///
+/// How a graceful shutdown is carried out
+///
+pub struct ShutdownOptions {
+  /// Reason sent in the close frame
+  pub reason: String,
+  /// How long the close handshake may take, from sending the unsubscribe
+  /// to the server acknowledging the close frame
+  pub close_timeout: Duration,
+  /// How long to wait for the consumer to make room for the messages still
+  /// buffered, messages that do not fit in time are dropped
+  pub drain_timeout: Duration,
+}
+
+/// How long [`KiteTickerHandle::close`] waits for the server to acknowledge
+/// the close frame
+pub(crate) const CLOSE_TIMEOUT: Duration = Duration::from_secs(5);
+
+impl Default for ShutdownOptions {
+  fn default() -> Self {
+    Self {
+      reason: "client shutdown".to_string(),
+      close_timeout: CLOSE_TIMEOUT,
+      drain_timeout: Duration::from_secs(5),
+    }
+  }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+///
+/// What a graceful shutdown did
+///
+pub struct ShutdownSummary {
+  /// Tokens that were subscribed when the shutdown started
+  pub unsubscribed: Vec<u32>,
+  /// The server answered the close frame within the close timeout
+  pub close_acknowledged: bool,
+  /// Messages handed to the consumer while shutting down
+  pub drained: usize,
+  /// Messages dropped because the consumer was gone or did not make room
+  /// for them within the drain timeout
+  pub dropped: usize,
+}
+
+#[derive(Debug)]
 ///
 /// The WebSocket client for connecting to Kite Connect's streaming quotes service.
 ///
+/// The socket is owned by a background task, so subscription changes never
+/// wait on inbound messages.
+///
 pub struct KiteTickerAsync {
-  #[allow(dead_code)]
-  api_key: String,
-  #[allow(dead_code)]
-  access_token: String,
-  ws_stream: Arc<Mutex<WebSocketStream<MaybeTlsStream<TcpStream>>>>,
+  handle: KiteTickerHandle,
+  messages: mpsc::Receiver<Result<TickerMessage, KiteTickerError>>,
 }
 
 impl KiteTickerAsync {
@@ -27,137 +164,298 @@ impl KiteTickerAsync {
   pub async fn connect(
     api_key: &str,
     access_token: &str,
-  ) -> Result<Self, String> {
-    let socket_url = format!(
-      "wss://{}?api_key={}&access_token={}",
-      "ws.kite.trade", api_key, access_token
+  ) -> Result<Self, KiteTickerError> {
+    Self::builder()
+      .credentials(api_key, access_token)
+      .connect()
+      .await
+  }
+
+  /// Configure the connection before establishing it
+  pub fn builder() -> KiteTickerAsyncBuilder {
+    KiteTickerAsyncBuilder::default()
+  }
+
+  pub(crate) fn new(
+    config: TickerConfig,
+    credentials: Credentials,
+    ws_stream: WsStream,
+  ) -> Self {
+    let config = Arc::new(config);
+    let (command_tx, command_rx) = mpsc::unbounded_channel();
+    let (message_tx, message_rx) = mpsc::channel(config.message_buffer);
+    let shared = Arc::new(Shared::default());
+    let worker = Worker::new(
+      config.clone(),
+      credentials,
+      command_rx,
+      message_tx,
+      shared.clone(),
     );
-    let url = url::Url::parse(socket_url.as_str()).unwrap();
+    let run = worker.run(ws_stream);
+    tokio::spawn(crate::trace::instrument!(
+      run,
+      "ticker",
+      endpoint = %config.endpoint
+    ));
 
-    let (ws_stream, _) = connect_async(url).await.map_err(|e| e.to_string())?;
+    KiteTickerAsync {
+      handle: KiteTickerHandle {
+        config,
+        commands: command_tx,
+        shared,
+      },
+      messages: message_rx,
+    }
+  }
 
-    Ok(KiteTickerAsync {
-      api_key: api_key.to_string(),
-      access_token: access_token.to_string(),
-      ws_stream: Arc::new(Mutex::new(ws_stream)),
-    })
+  /// Cloneable handle to control the connection from other tasks
+  pub fn handle(&self) -> KiteTickerHandle {
+    self.handle.clone()
+  }
+
+  pub(crate) fn into_parts(
+    self,
+  ) -> (
+    KiteTickerHandle,
+    mpsc::Receiver<Result<TickerMessage, KiteTickerError>>,
+  ) {
+    (self.handle, self.messages)
   }
 
   /// Subscribes the client to a list of instruments
   pub async fn subscribe(
-    mut self,
+    self,
     instrument_tokens: &[u32],
     mode: Option<Mode>,
-  ) -> Result<KiteTickerSubscriber, String> {
-    self
-      .subscribe_cmd(instrument_tokens, mode.clone())
-      .await
-      .expect("failed to subscribe");
-    let st = instrument_tokens
-      .to_vec()
-      .iter()
-      .map(|t| (t.clone(), mode.to_owned().unwrap_or_default()))
-      .collect();
+  ) -> Result<KiteTickerSubscriber, KiteTickerError> {
+    self.handle.subscribe(instrument_tokens, mode).await?;
 
     Ok(KiteTickerSubscriber {
-      ticker: self,
-      subscribed_tokens: st,
+      handle: self.handle,
+      messages: self.messages,
     })
   }
 
+  /// Measure the round trip to the server with a WebSocket ping
+  ///
+  /// Fails with [`KiteTickerError::Timeout`] when the pong does not arrive
+  /// within [`crate::KiteTickerAsyncBuilder::ping_timeout`].
+  pub async fn ping(&self) -> Result<Duration, KiteTickerError> {
+    self.handle.ping().await
+  }
+
   /// Close the websocket connection
-  pub async fn close(&mut self) -> Result<(), String> {
-    let mut ws_stream = self.ws_stream.lock().await;
-    ws_stream.close(None).await.map_err(|x| x.to_string())?;
-    Ok(())
+  pub async fn close(&mut self) -> Result<(), KiteTickerError> {
+    self.handle.close().await
   }
+}
 
-  async fn subscribe_cmd(
-    &mut self,
-    instrument_tokens: &[u32],
-    mode: Option<Mode>,
-  ) -> Result<(), String> {
-    let mut msgs = iter(vec![
-      Ok(Message::Text(
-        Request::subscribe(instrument_tokens.to_vec()).to_string(),
-      )),
-      Ok(Message::Text(
-        Request::mode(mode.unwrap_or_default(), instrument_tokens.to_vec())
-          .to_string(),
-      )),
-    ]);
+#[derive(Debug, Clone)]
+///
+/// Cloneable control handle sharing one subscription state with the ticker
+///
+pub struct KiteTickerHandle {
+  config: Arc<TickerConfig>,
+  commands: mpsc::UnboundedSender<Command>,
+  shared: Arc<Shared>,
+}
 
-    let mut ws_stream = self.ws_stream.lock().await;
+impl KiteTickerHandle {
+  async fn request(
+    &self,
+    command: impl FnOnce(Reply) -> Command,
+  ) -> Result<(), KiteTickerError> {
+    let (reply, response) = oneshot::channel();
+    self
+      .commands
+      .send(command(reply))
+      .map_err(|_| KiteTickerError::TickerClosed)?;
+    response.await.map_err(|_| KiteTickerError::TickerClosed)?
+  }
 
-    ws_stream
-      .send_all(msgs.by_ref())
-      .await
-      .expect("failed to send subscription message");
+  /// Get the list of subscribed instruments
+  pub fn get_subscribed(&self) -> Vec<u32> {
+    self
+      .shared
+      .subscriptions
+      .read()
+      .unwrap()
+      .keys()
+      .copied()
+      .collect()
+  }
 
-    Ok(())
+  /// Get the subscribed instruments along with their modes
+  pub fn subscriptions(&self) -> HashMap<u32, Mode> {
+    self.shared.subscriptions.read().unwrap().clone()
   }
 
-  async fn unsubscribe_cmd(
-    &mut self,
-    instrument_tokens: &[u32],
-  ) -> Result<(), String> {
-    let mut ws_stream = self.ws_stream.lock().await;
-    ws_stream
-      .send(Message::Text(
-        Request::unsubscribe(instrument_tokens.to_vec()).to_string(),
-      ))
+  /// Subscribe to new tokens
+  pub async fn subscribe(
+    &self,
+    tokens: &[u32],
+    mode: Option<Mode>,
+  ) -> Result<(), KiteTickerError> {
+    let mode = mode.unwrap_or_else(|| self.config.default_mode.clone());
+    self
+      .request(|reply| Command::Subscribe {
+        tokens: tokens.to_vec(),
+        mode,
+        reply,
+      })
       .await
-      .expect("failed to send unsubscribe message");
-    Ok(())
   }
 
-  async fn set_mode_cmd(
-    &mut self,
+  /// Change the mode of the subscribed instrument tokens, if input is empty
+  /// then the mode of all subscribed tokens is changed
+  pub async fn set_mode(
+    &self,
     instrument_tokens: &[u32],
     mode: Mode,
-  ) -> Result<(), String> {
-    let mut ws_stream = self.ws_stream.lock().await;
-    ws_stream
-      .send(Message::Text(
-        Request::mode(mode, instrument_tokens.to_vec()).to_string(),
-      ))
+  ) -> Result<(), KiteTickerError> {
+    self
+      .request(|reply| Command::SetMode {
+        tokens: instrument_tokens.to_vec(),
+        mode,
+        reply,
+      })
+      .await
+  }
+
+  /// Unsubscribe provided subscribed tokens, if input is empty then all subscribed tokens will unsubscribed
+  ///
+  /// Tokens in the input which are not part of the subscribed tokens will be ignored.
+  pub async fn unsubscribe(
+    &self,
+    instrument_tokens: &[u32],
+  ) -> Result<(), KiteTickerError> {
+    self
+      .request(|reply| Command::Unsubscribe {
+        tokens: instrument_tokens.to_vec(),
+        reply,
+      })
       .await
-      .expect("failed to send set mode message");
-    Ok(())
+  }
+
+  /// Unsubscribe without waiting for the request to be sent, for use where
+  /// awaiting is not possible such as in `Drop`
+  pub(crate) fn unsubscribe_detached(&self, instrument_tokens: Vec<u32>) {
+    let (reply, _) = oneshot::channel();
+    let _ = self.commands.send(Command::Unsubscribe {
+      tokens: instrument_tokens,
+      reply,
+    });
+  }
+
+  /// Close the websocket connection
+  ///
+  /// The stream of messages ends once the server acknowledges the close
+  /// frame, or after 5 seconds if it does not.
+  pub async fn close(&self) -> Result<(), KiteTickerError> {
+    self.request(|reply| Command::Close { reply }).await
+  }
+
+  /// Reconnect with new credentials, such as the access token of a fresh
+  /// login, restoring every subscription with its mode
+  ///
+  /// The new connection is opened before the current one is closed, and if
+  /// it fails the current connection keeps streaming. While disconnected the
+  /// credentials are used for the next reconnect attempt. A credentials
+  /// provider, if set, is still asked on later reconnects.
+  pub async fn update_credentials(
+    &self,
+    credentials: Credentials,
+  ) -> Result<(), KiteTickerError> {
+    self
+      .request(|reply| Command::UpdateCredentials { credentials, reply })
+      .await
+  }
+
+  /// Shut the connection down gracefully
+  ///
+  /// Every token is unsubscribed, a close frame carrying the reason is sent
+  /// and the server's acknowledgement awaited, then the messages still
+  /// buffered are handed to the consumer before its stream ends. The
+  /// consumer should keep reading from another task meanwhile.
+  pub async fn shutdown(
+    &self,
+    options: ShutdownOptions,
+  ) -> Result<ShutdownSummary, KiteTickerError> {
+    let (reply, response) = oneshot::channel();
+    self
+      .commands
+      .send(Command::Shutdown { options, reply })
+      .map_err(|_| KiteTickerError::TickerClosed)?;
+    response.await.map_err(|_| KiteTickerError::TickerClosed)
+  }
+
+  /// Shut the connection down gracefully, see
+  /// [`KiteTickerHandle::shutdown`], once the token is cancelled
+  ///
+  /// Fails with [`KiteTickerError::TickerClosed`] if the ticker closes on
+  /// its own first.
+  pub async fn shutdown_on(
+    &self,
+    token: CancellationToken,
+    options: ShutdownOptions,
+  ) -> Result<ShutdownSummary, KiteTickerError> {
+    tokio::select! {
+      _ = token.cancelled() => self.shutdown(options).await,
+      _ = self.commands.closed() => Err(KiteTickerError::TickerClosed),
+    }
+  }
+
+  /// Measure the round trip to the server with a WebSocket ping
+  ///
+  /// Fails with [`KiteTickerError::Timeout`] when the pong does not arrive
+  /// within [`crate::KiteTickerAsyncBuilder::ping_timeout`].
+  pub async fn ping(&self) -> Result<Duration, KiteTickerError> {
+    let (reply, response) = oneshot::channel();
+    self
+      .commands
+      .send(Command::Ping { reply })
+      .map_err(|_| KiteTickerError::TickerClosed)?;
+    response.await.map_err(|_| KiteTickerError::TickerClosed)?
+  }
+
+  /// Current state of the connection
+  pub fn state(&self) -> ConnectionState {
+    self.shared.state.lock().unwrap().clone()
+  }
+
+  /// Round trip of the most recently answered ping
+  pub fn last_rtt(&self) -> Option<Duration> {
+    *self.shared.last_rtt.lock().unwrap()
+  }
+
+  /// Registry counting what the connection received
+  pub fn metrics(&self) -> Metrics {
+    self.config.metrics.clone()
   }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug)]
 ///
 /// The Websocket client that entered in a pub/sub mode once the client subscribed to a list of instruments
 ///
+/// Messages can be awaited one by one with
+/// [`KiteTickerSubscriber::next_message`], or consumed as a [`Stream`].
+///
 pub struct KiteTickerSubscriber {
-  ticker: KiteTickerAsync,
-  subscribed_tokens: HashMap<u32, Mode>,
+  handle: KiteTickerHandle,
+  messages: mpsc::Receiver<Result<TickerMessage, KiteTickerError>>,
 }
 
 impl KiteTickerSubscriber {
-  /// Get the list of subscribed instruments
-  pub fn get_subscribed(&self) -> Vec<u32> {
-    self
-      .subscribed_tokens
-      .clone()
-      .into_keys()
-      .collect::<Vec<_>>()
+  /// Cloneable handle to control the connection from other tasks
+  pub fn handle(&self) -> KiteTickerHandle {
+    self.handle.clone()
   }
 
-  /// get all tokens common between subscribed tokens and input tokens
-  /// and if the input is empty then all subscribed tokens will be unsubscribed
-  fn get_subscribed_or(&self, tokens: &[u32]) -> Vec<u32> {
-    if tokens.len() == 0 {
-      self.get_subscribed()
-    } else {
-      tokens
-        .iter()
-        .filter(|t| self.subscribed_tokens.contains_key(t))
-        .map(|t| t.clone())
-        .collect::<Vec<_>>()
-    }
+  /// Get the list of subscribed instruments
+  pub fn get_subscribed(&self) -> Vec<u32> {
+    self.handle.get_subscribed()
   }
 
   /// Subscribe to new tokens
@@ -165,15 +463,8 @@ impl KiteTickerSubscriber {
     &mut self,
     tokens: &[u32],
     mode: Option<Mode>,
-  ) -> Result<(), String> {
-    self.subscribed_tokens.extend(
-      tokens
-        .iter()
-        .map(|t| (t.clone(), mode.clone().unwrap_or_default())),
-    );
-    let tks = self.get_subscribed();
-    self.ticker.subscribe_cmd(tks.as_slice(), None).await?;
-    Ok(())
+  ) -> Result<(), KiteTickerError> {
+    self.handle.subscribe(tokens, mode).await
   }
 
   /// Change the mode of the subscribed instrument tokens
@@ -181,9 +472,8 @@ impl KiteTickerSubscriber {
     &mut self,
     instrument_tokens: &[u32],
     mode: Mode,
-  ) -> Result<(), String> {
-    let tokens = self.get_subscribed_or(instrument_tokens);
-    self.ticker.set_mode_cmd(tokens.as_slice(), mode).await
+  ) -> Result<(), KiteTickerError> {
+    self.handle.set_mode(instrument_tokens, mode).await
   }
 
   /// Unsubscribe provided subscribed tokens, if input is empty then all subscribed tokens will unsubscribed
@@ -192,88 +482,81 @@ impl KiteTickerSubscriber {
   pub async fn unsubscribe(
     &mut self,
     instrument_tokens: &[u32],
-  ) -> Result<(), String> {
-    let tokens = self.get_subscribed_or(instrument_tokens);
-    match self.ticker.unsubscribe_cmd(tokens.as_slice()).await{
-      Ok(_) => {
-        self.subscribed_tokens.retain(|k, _| !tokens.contains(k));
-        Ok(())
-      },
-      Err(e) => Err(e)
-    }
+  ) -> Result<(), KiteTickerError> {
+    self.handle.unsubscribe(instrument_tokens).await
   }
 
   /// Get the next message from the server, waiting if necessary.
   /// If the result is None then server is terminated
+  ///
+  /// The connection itself is reported through [`TickerMessage::Connected`]
+  /// and [`TickerMessage::Disconnected`]. When it drops it is re-established
+  /// according to the ticker's [`ReconnectPolicy`], and the progress is
+  /// reported through [`TickerMessage::Reconnecting`],
+  /// [`TickerMessage::Resubscribed`] and [`TickerMessage::GaveUp`], or by
+  /// the error that stopped it.
   pub async fn next_message(
     &mut self,
-  ) -> Result<Option<TickerMessage>, String> {
-    let mut ws_stream = self.ticker.ws_stream.lock().await;
-    match ws_stream.next().await {
-      Some(message) => match message {
-        Ok(msg) => Ok(self.process_message(msg)),
-        Err(e) => Err(e.to_string()),
-      },
-      None => Ok(None),
-    }
+  ) -> Result<Option<TickerMessage>, KiteTickerError> {
+    self.messages.recv().await.transpose()
   }
 
-  fn process_message(&self, message: Message) -> Option<TickerMessage> {
-    match message {
-      Message::Text(text_message) => self.process_text_message(text_message),
-      Message::Binary(ref binary_message) => {
-        if binary_message.len() < 2 {
-          return Some(TickerMessage::Ticks(vec![]));
-        } else {
-          self.process_binary(binary_message.as_slice())
-        }
-      }
-      Message::Close(closing_message) => closing_message.map(|c| {
-        TickerMessage::ClosingMessage(json!({
-          "code": c.code.to_string(),
-          "reason": c.reason.to_string()
-        }))
-      }),
-      Message::Ping(_) => unimplemented!(),
-      Message::Pong(_) => unimplemented!(),
-      Message::Frame(_) => unimplemented!(),
-    }
+  /// Measure the round trip to the server with a WebSocket ping
+  ///
+  /// Fails with [`KiteTickerError::Timeout`] when the pong does not arrive
+  /// within [`crate::KiteTickerAsyncBuilder::ping_timeout`].
+  pub async fn ping(&self) -> Result<Duration, KiteTickerError> {
+    self.handle.ping().await
   }
 
-  fn process_binary(&self, binary_message: &[u8]) -> Option<TickerMessage> {
-    // 0 - 2 : number of packets in the message
-    let num_packets =
-      i16::from_be_bytes(binary_message[0..=1].try_into().unwrap()) as usize;
-    if num_packets > 0 {
-      Some(TickerMessage::Ticks(
-        (0..num_packets)
-          .into_iter()
-          .fold((vec![], 2), |(mut acc, start), _| {
-            // start - start + 2 : length of the packet
-            let packet_len = packet_length(&binary_message[start..start + 2]);
-            let next_start = start + 2 + packet_len;
-            let tick = Tick::from(&binary_message[start + 2..next_start]);
-            acc.push(TickMessage::new(tick.instrument_token, tick));
-            (acc, next_start)
-          })
-          .0,
-      ))
-    } else {
-      None
-    }
+  pub async fn close(&mut self) -> Result<(), KiteTickerError> {
+    self.handle.close().await
   }
 
-  fn process_text_message(
-    &self,
-    text_message: String,
-  ) -> Option<TickerMessage> {
-    serde_json::from_str::<TextMessage>(&text_message)
-      .map(|x| x.into())
-      .ok()
+  /// Stream of quotes, one item per instrument, skipping every other message
+  ///
+  /// Use [`KiteTickerSubscriber::handle`] beforehand to keep control of the
+  /// subscriptions.
+  pub fn ticks(self) -> impl Stream<Item = TickMessage> {
+    self
+      .filter_map(|message| {
+        future::ready(match message {
+          Ok(TickerMessage::Ticks(ticks)) => {
+            Some(futures_util::stream::iter(ticks))
+          }
+          _ => None,
+        })
+      })
+      .flatten()
   }
 
-  pub async fn close(&mut self) -> Result<(), String> {
-    self.ticker.close().await
+  /// Stream of order postbacks, skipping every other message
+  pub fn order_postbacks(
+    self,
+  ) -> impl Stream<Item = Result<Order, KiteTickerError>> {
+    self.filter_map(|message| {
+      future::ready(match message {
+        Ok(TickerMessage::OrderPostback(order)) => Some(order),
+        _ => None,
+      })
+    })
+  }
+
+  /// Stream of errors raised while reading the feed, such as damaged
+  /// packets or a lost connection, skipping every other message
+  pub fn errors(self) -> impl Stream<Item = KiteTickerError> {
+    self.filter_map(|message| future::ready(message.err()))
+  }
+}
+
+impl Stream for KiteTickerSubscriber {
+  type Item = Result<TickerMessage, KiteTickerError>;
+
+  fn poll_next(
+    mut self: Pin<&mut Self>,
+    cx: &mut Context<'_>,
+  ) -> Poll<Option<Self::Item>> {
+    self.messages.poll_recv(cx)
   }
 }
 
@@ -282,9 +565,15 @@ mod tests {
   use std::time::Duration;
 
   use base64::{engine::general_purpose, Engine};
+  use chrono::TimeDelta;
 
-  use crate::{DepthItem, Mode, Tick, OHLC};
+  use super::ReconnectPolicy;
+  use crate::{
+    DecodeError, DecodeReason, DepthItem, EncodeError, EncodeReason, FrameView,
+    Mode, Price, Tick, TickMessage, TickView, OHLC,
+  };
 
+  #[allow(clippy::let_and_return)]
   fn load_packet(name: &str) -> Vec<u8> {
     let str =
       std::fs::read_to_string(format!("kiteconnect-mocks/{}.packet", name))
@@ -309,17 +598,17 @@ mod tests {
           is_index: false,
           last_traded_timestamp: None,
           exchange_timestamp: None,
-          last_price: Some(1573.15),
-          avg_traded_price: Some(1570.33),
+          last_price: Some(Price::new(157315, 2)),
+          avg_traded_price: Some(Price::new(157033, 2)),
           last_traded_qty: Some(1),
           total_buy_qty: Some(256511),
           total_sell_qty: Some(360503),
           volume_traded: Some(1175986),
           ohlc: Some(OHLC {
-            open: 1569.15,
-            high: 1575.0,
-            low: 1561.05,
-            close: 1567.8,
+            open: Price::new(156915, 2),
+            high: Price::new(157500, 2),
+            low: Price::new(156105, 2),
+            close: Price::new(156780, 2),
           }),
           oi_day_high: None,
           oi_day_low: None,
@@ -337,84 +626,82 @@ mod tests {
           instrument_token: 408065,
           is_tradable: true,
           is_index: false,
-          last_traded_timestamp: Some(Duration::from_secs(
+          last_traded_timestamp: Some(
             chrono::DateTime::parse_from_rfc3339("2021-07-05T10:41:27+05:30")
-              .unwrap()
-              .timestamp() as u64,
-          )),
-          exchange_timestamp: Some(Duration::from_secs(
+              .unwrap(),
+          ),
+          exchange_timestamp: Some(
             chrono::DateTime::parse_from_rfc3339("2021-07-05T10:41:27+05:30")
-              .unwrap()
-              .timestamp() as u64,
-          )),
-          last_price: Some(1573.7),
-          avg_traded_price: Some(1570.37),
+              .unwrap(),
+          ),
+          last_price: Some(Price::new(157370, 2)),
+          avg_traded_price: Some(Price::new(157037, 2)),
           last_traded_qty: Some(7),
           total_buy_qty: Some(256443),
           total_sell_qty: Some(363009),
           volume_traded: Some(1192471),
           ohlc: Some(OHLC {
-            open: 1569.15,
-            high: 1575.0,
-            low: 1561.05,
-            close: 1567.8,
+            open: Price::new(156915, 2),
+            high: Price::new(157500, 2),
+            low: Price::new(156105, 2),
+            close: Price::new(156780, 2),
           }),
           oi_day_high: Some(0),
           oi_day_low: Some(0),
           oi: Some(0),
-          net_change: Some(5.900000000000091),
+          net_change: Some(Price::new(590, 2)),
           depth: Some(crate::Depth {
             buy: [
               DepthItem {
                 qty: 5,
-                price: 1573.4,
+                price: Price::new(157340, 2),
                 orders: 1,
               },
               DepthItem {
                 qty: 140,
-                price: 1573.0,
+                price: Price::new(157300, 2),
                 orders: 2,
               },
               DepthItem {
                 qty: 2,
-                price: 1572.95,
+                price: Price::new(157295, 2),
                 orders: 1,
               },
               DepthItem {
                 qty: 219,
-                price: 1572.9,
+                price: Price::new(157290, 2),
                 orders: 7,
               },
               DepthItem {
                 qty: 50,
-                price: 1572.85,
+                price: Price::new(157285, 2),
                 orders: 1,
               },
             ],
             sell: [
               DepthItem {
                 qty: 172,
-                price: 1573.7,
+                price: Price::new(157370, 2),
                 orders: 3,
               },
               DepthItem {
                 qty: 44,
-                price: 1573.75,
+                price: Price::new(157375, 2),
                 orders: 3,
               },
               DepthItem {
                 qty: 302,
-                price: 1573.85,
+                price: Price::new(157385, 2),
                 orders: 3,
               },
               DepthItem {
                 qty: 141,
-                price: 1573.9,
+                price: Price::new(157390, 2),
                 orders: 2,
               },
               DepthItem {
                 qty: 724,
-                price: 1573.95,
+                price: Price::new(157395, 2),
                 orders: 5,
               },
             ],
@@ -428,9 +715,330 @@ mod tests {
   fn test_quotes() {
     let data = setup();
     for (name, packet, expected) in data {
-      let tick = Tick::try_from(packet.as_slice());
-      assert_eq!(tick.is_err(), false);
-      assert_eq!(tick.unwrap(), expected, "Testing {}", name);
+      let tick = Tick::try_from(packet.as_slice()).unwrap();
+      assert_eq!(tick, expected, "Testing {}", name);
     }
   }
+
+  #[test]
+  fn test_latency() {
+    let (_, _, full) = setup().pop().unwrap();
+    let exchange_timestamp = full.exchange_timestamp.unwrap();
+    assert_eq!(exchange_timestamp.offset().local_minus_utc(), 19800);
+    let received_at = exchange_timestamp + TimeDelta::milliseconds(1500);
+    let tick = TickMessage::new(full.instrument_token, full, received_at);
+    assert_eq!(tick.latency(), Some(TimeDelta::milliseconds(1500)));
+  }
+
+  #[test]
+  fn test_damaged_quotes() {
+    for (name, packet, _) in setup() {
+      for len in 0..packet.len() {
+        let _ = Tick::try_from(&packet[..len]);
+      }
+      assert_eq!(
+        Tick::try_from(&packet[..5]),
+        Err(DecodeError {
+          offset: 0,
+          reason: DecodeReason::Truncated {
+            needed: 8,
+            available: 5
+          }
+        }),
+        "Testing {}",
+        name
+      );
+    }
+
+    // lengths that match no mode, such as a truncated full packet
+    let (_, full, _) = setup().pop().unwrap();
+    let unknown = |len| DecodeError {
+      offset: 0,
+      reason: DecodeReason::UnknownLength(len),
+    };
+    for len in [20, 28, 32, 45, 100, 183] {
+      let packet = full.iter().copied().cycle().take(len).collect::<Vec<_>>();
+      assert_eq!(Tick::try_from(packet.as_slice()), Err(unknown(len)));
+      assert_eq!(TickView::new(&packet), Err(unknown(len)));
+    }
+    let mut index = full[..44].to_vec();
+    // token of an index, whose packets are 8, 28 or 32 bytes long
+    index[3] = 9;
+    assert_eq!(Tick::try_from(index.as_slice()), Err(unknown(44)));
+    assert_eq!(Tick::try_from(&index[..28]).unwrap().mode, Mode::Quote);
+
+    let mut frame = 2_u16.to_be_bytes().to_vec();
+    for packet in [&full[..100], &full[..8]] {
+      frame.extend((packet.len() as u16).to_be_bytes());
+      frame.extend(packet);
+    }
+    let mut packets = FrameView::new(&frame).packets();
+    assert_eq!(
+      packets.next().unwrap(),
+      Err(DecodeError {
+        offset: 4,
+        reason: DecodeReason::UnknownLength(100)
+      })
+    );
+    assert_eq!(packets.next().unwrap().unwrap().mode(), Mode::LTP);
+
+    let (_, mut packet, _) = setup().pop().unwrap();
+    // quantity of the second buy entry in the market depth
+    packet[76..80].copy_from_slice(&(-1_i32).to_be_bytes());
+    assert_eq!(
+      Tick::try_from(packet.as_slice()),
+      Err(DecodeError {
+        offset: 76,
+        reason: DecodeReason::Negative("quantity")
+      })
+    );
+  }
+
+  #[test]
+  fn test_views() {
+    for (name, packet, expected) in setup() {
+      let view = TickView::new(&packet).unwrap();
+      assert_eq!(view.instrument_token(), expected.instrument_token);
+      assert_eq!(view.mode(), expected.mode, "Testing {}", name);
+      assert_eq!(view.last_price(), expected.last_price);
+      assert_eq!(view.volume_traded(), expected.volume_traded);
+      assert_eq!(view.ohlc(), expected.ohlc);
+      assert_eq!(view.exchange_timestamp(), expected.exchange_timestamp);
+      assert_eq!(view.net_change(), expected.net_change);
+      assert_eq!(view.depth().unwrap(), expected.depth);
+      assert_eq!(view.to_tick().unwrap(), expected, "Testing {}", name);
+    }
+
+    let packets = setup()
+      .into_iter()
+      .map(|(_, packet, _)| packet)
+      .collect::<Vec<_>>();
+    let mut frame = (packets.len() as u16 + 1).to_be_bytes().to_vec();
+    for packet in packets.iter().chain([&vec![0; 5]]) {
+      frame.extend((packet.len() as u16).to_be_bytes());
+      frame.extend(packet);
+    }
+    let frame = FrameView::new(&frame);
+    assert!(!frame.is_heartbeat());
+    assert_eq!(frame.packet_count(), 3);
+    let views = frame.packets().collect::<Vec<_>>();
+    assert_eq!(views[0].as_ref().unwrap().offset(), 4);
+    assert_eq!(views[1].as_ref().unwrap().mode(), Mode::Full);
+    assert_eq!(
+      views[2],
+      Err(DecodeError {
+        offset: 2 + 2 + 44 + 2 + 184 + 2,
+        reason: DecodeReason::Truncated {
+          needed: 8,
+          available: 5
+        }
+      })
+    );
+    let mut packets = frame.into_iter();
+    packets.next();
+    let depth = packets.next().unwrap().unwrap().depth().unwrap().unwrap();
+    assert_eq!(depth.sell[4].qty, 724);
+
+    // a length prefix running past the end stops the iteration
+    let frame = [0, 2, 0, 8, 0, 0, 0, 1];
+    let mut packets = FrameView::new(&frame).packets();
+    assert!(packets.next().unwrap().is_err());
+    assert!(packets.next().is_none());
+    assert!(FrameView::new(&[0]).is_heartbeat());
+  }
+
+  #[test]
+  fn test_encode() {
+    for (name, packet, expected) in setup() {
+      assert_eq!(expected.encode().unwrap(), packet, "Testing {}", name);
+    }
+
+    let ticks = setup().into_iter().map(|(_, _, t)| t).collect::<Vec<_>>();
+    let frame = Tick::encode_frame(&ticks).unwrap();
+    let decoded = FrameView::new(&frame)
+      .packets()
+      .map(|view| view.unwrap().to_tick().unwrap())
+      .collect::<Vec<_>>();
+    assert_eq!(decoded, ticks);
+
+    let mut tick = ticks[0].clone();
+    tick.last_price = Some(Price::new(1573151, 3));
+    assert_eq!(
+      tick.encode(),
+      Err(EncodeError {
+        field: "last_price",
+        reason: EncodeReason::Precision
+      })
+    );
+    tick.last_price = None;
+    tick.volume_traded = Some(u32::MAX);
+    assert_eq!(
+      tick.encode().unwrap_err().to_string(),
+      "volume_traded does not fit in a packet"
+    );
+  }
+
+  #[cfg(feature = "serde")]
+  const PYKITECONNECT_TICKS: &str = r#"[
+    {
+      "tradable": true, "mode": "full", "instrument_token": 408065,
+      "last_price": 1573.7, "last_traded_quantity": 7,
+      "average_traded_price": 1570.37, "volume_traded": 1192471,
+      "total_buy_quantity": 256443, "total_sell_quantity": 363009,
+      "ohlc": {"open": 1569.15, "high": 1575.0, "low": 1561.05, "close": 1567.8},
+      "change": 0.3763235106518747,
+      "last_trade_time": "2021-07-05 10:41:27",
+      "oi": 0, "oi_day_high": 0, "oi_day_low": 0,
+      "exchange_timestamp": "2021-07-05 10:41:27",
+      "depth": {
+        "buy": [
+          {"quantity": 5, "price": 1573.4, "orders": 1},
+          {"quantity": 140, "price": 1573.0, "orders": 2},
+          {"quantity": 2, "price": 1572.95, "orders": 1},
+          {"quantity": 219, "price": 1572.9, "orders": 7},
+          {"quantity": 50, "price": 1572.85, "orders": 1}
+        ],
+        "sell": [
+          {"quantity": 172, "price": 1573.7, "orders": 3},
+          {"quantity": 44, "price": 1573.75, "orders": 3},
+          {"quantity": 302, "price": 1573.85, "orders": 3},
+          {"quantity": 141, "price": 1573.9, "orders": 2},
+          {"quantity": 724, "price": 1573.95, "orders": 5}
+        ]
+      }
+    },
+    {
+      "tradable": false, "mode": "quote", "instrument_token": 256265,
+      "last_price": 15812.35,
+      "ohlc": {"high": 15835.0, "low": 15738.4, "open": 15760.1, "close": 15722.2},
+      "change": 0.5733930365979293
+    }
+  ]"#;
+
+  #[cfg(feature = "serde")]
+  #[test]
+  fn test_serde() {
+    use crate::{TickMessage, TickerMessage};
+
+    let mut ticks = setup().into_iter().map(|(_, _, t)| t);
+    let (quote, full) = (ticks.next().unwrap(), ticks.next().unwrap());
+
+    let json = serde_json::to_value(&full).unwrap();
+    assert_eq!(json["mode"], "full");
+    assert!(json.get("exchange").is_none() && json.get("is_index").is_none());
+    assert_eq!(json["tradable"], true);
+    assert_eq!(json["last_price"], 1573.7);
+    assert_eq!(json["last_traded_quantity"], 7);
+    assert_eq!(json["ohlc"]["close"], 1567.8);
+    let change = json["change"].as_f64().unwrap();
+    assert!((change - 0.3763235106518747).abs() < 1e-12);
+    assert_eq!(json["last_trade_time"], "2021-07-05T10:41:27+05:30");
+    assert_eq!(json["exchange_timestamp"], "2021-07-05T10:41:27+05:30");
+    assert_eq!(json["depth"]["buy"][1]["quantity"], 140);
+    assert_eq!(json["depth"]["sell"][4]["price"], 1573.95);
+    assert_eq!(serde_json::from_value::<Tick>(json).unwrap(), full);
+
+    let json = serde_json::to_value(&quote).unwrap();
+    for key in ["depth", "oi", "exchange_timestamp", "change"] {
+      assert!(json.get(key).is_none(), "{} in a quote", key);
+    }
+    assert_eq!(serde_json::from_value::<Tick>(json).unwrap(), quote);
+
+    // ticks of pykiteconnect's on_ticks, written with
+    // `json.dumps(ticks, default=str)`
+    let ticks: Vec<Tick> = serde_json::from_str(PYKITECONNECT_TICKS).unwrap();
+    assert_eq!(ticks[0], full);
+    let index = &ticks[1];
+    assert_eq!(index.exchange, crate::Exchange::INDICES);
+    assert!(index.is_index && !index.is_tradable);
+    assert_eq!(index.mode, Mode::Quote);
+    assert_eq!(index.net_change, Some(Price::new(9015, 2)));
+    assert_eq!(index.exchange_timestamp, None);
+
+    let received_at =
+      chrono::DateTime::parse_from_rfc3339("2021-07-05T10:41:28.5+05:30")
+        .unwrap();
+    let tick = TickMessage::new(408065, quote.clone(), received_at);
+    let message = TickerMessage::Ticks(vec![tick]);
+    let json = serde_json::to_value(&message).unwrap();
+    assert_eq!(json["type"], "ticks");
+    assert_eq!(json["data"][0]["instrument_token"], 408065);
+    assert_eq!(
+      json["data"][0]["received_at"],
+      "2021-07-05T10:41:28.500+05:30"
+    );
+    let ticks: Vec<TickMessage> =
+      serde_json::from_value(json["data"].clone()).expect("ticks read back");
+    assert_eq!(ticks[0].content, quote);
+    assert_eq!(ticks[0].received_at, received_at);
+
+    let message = TickerMessage::Disconnected {
+      reason: crate::DisconnectReason::Lost(Some(
+        crate::KiteTickerError::NotConnected,
+      )),
+    };
+    let json = serde_json::to_value(&message).unwrap();
+    assert_eq!(json["data"]["reason"]["type"], "lost");
+    assert_eq!(
+      json["data"]["reason"]["data"],
+      crate::KiteTickerError::NotConnected.to_string()
+    );
+
+    let message = TickerMessage::Connected {
+      at: chrono::DateTime::UNIX_EPOCH,
+      endpoint: "wss://ws.kite.trade".to_string(),
+    };
+    let json = serde_json::to_value(&message).unwrap();
+    assert_eq!(json["data"]["at"], "1970-01-01T05:30:00+05:30");
+  }
+
+  #[test]
+  fn test_reconnect_delay() {
+    let policy = ReconnectPolicy {
+      jitter: 0.0,
+      ..Default::default()
+    };
+    assert_eq!(policy.delay_for(1), Duration::from_millis(500));
+    assert_eq!(policy.delay_for(3), Duration::from_secs(2));
+    assert_eq!(policy.delay_for(20), policy.max_delay);
+
+    let policy = ReconnectPolicy::default();
+    for attempt in 1..10 {
+      let delay = policy.delay_for(attempt);
+      let upper = ReconnectPolicy {
+        jitter: 0.0,
+        ..policy.clone()
+      }
+      .delay_for(attempt);
+      assert!(delay <= upper);
+      assert!(delay >= upper.mul_f64(1.0 - policy.jitter));
+    }
+    assert!(!policy.exhausted(50));
+    assert!(policy.exhausted(51));
+
+    let policy = ReconnectPolicy {
+      jitter: f64::NAN,
+      ..Default::default()
+    };
+    assert_eq!(policy.delay_for(1), Duration::from_millis(500));
+
+    // delays too large for a `Duration` stop at the bound
+    let policy = ReconnectPolicy {
+      max_retries: None,
+      max_delay: Duration::MAX,
+      ..Default::default()
+    };
+    assert!(policy.delay_for(u32::MAX) >= Duration::from_secs(u64::MAX / 2));
+    let policy = ReconnectPolicy {
+      multiplier: f64::INFINITY,
+      jitter: 0.0,
+      ..policy
+    };
+    assert_eq!(policy.delay_for(2), Duration::MAX);
+    let policy = ReconnectPolicy {
+      initial_delay: Duration::ZERO,
+      ..policy
+    };
+    assert_eq!(policy.delay_for(2), Duration::MAX);
+  }
 }
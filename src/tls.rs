@@ -0,0 +1,175 @@
+use std::fmt;
+
+use base64::Engine;
+pub use tokio_tungstenite::Connector;
+
+use crate::KiteTickerError;
+
+#[cfg(not(any(feature = "native-tls", feature = "__rustls-tls")))]
+compile_error!(
+  "enable one of the `native-tls`, `rustls-tls-webpki-roots` or \
+   `rustls-tls-native-roots` features"
+);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+///
+/// Root certificate trusted when verifying the server
+///
+pub struct Certificate {
+  der: Vec<u8>,
+}
+
+impl Certificate {
+  /// Certificate in DER format
+  pub fn from_der(der: impl Into<Vec<u8>>) -> Self {
+    Self { der: der.into() }
+  }
+
+  /// First certificate of a PEM file
+  pub fn from_pem(pem: &[u8]) -> Result<Self, KiteTickerError> {
+    Self::from_pem_bundle(pem)?
+      .into_iter()
+      .next()
+      .ok_or_else(|| KiteTickerError::Config("no certificate in PEM".into()))
+  }
+
+  /// Every certificate of a PEM file, such as a CA bundle
+  pub fn from_pem_bundle(pem: &[u8]) -> Result<Vec<Self>, KiteTickerError> {
+    let pem = String::from_utf8_lossy(pem);
+    let mut certificates = vec![];
+    let mut body: Option<String> = None;
+    for line in pem.lines().map(str::trim) {
+      match (line, body.as_mut()) {
+        ("-----BEGIN CERTIFICATE-----", None) => body = Some(String::new()),
+        ("-----END CERTIFICATE-----", Some(encoded)) => {
+          let der = base64::engine::general_purpose::STANDARD
+            .decode(encoded.as_bytes())
+            .map_err(|e| {
+              KiteTickerError::Config(format!("invalid PEM certificate: {}", e))
+            })?;
+          certificates.push(Self::from_der(der));
+          body = None;
+        }
+        (line, Some(encoded)) => encoded.push_str(line),
+        _ => {}
+      }
+    }
+    Ok(certificates)
+  }
+}
+
+#[derive(Clone)]
+///
+/// How the TLS connection to the server is set up
+///
+pub(crate) struct TlsSettings {
+  /// Trust the roots of the enabled backend
+  pub(crate) built_in_roots: bool,
+  /// Roots trusted on top of, or instead of, the built-in ones
+  pub(crate) roots: Vec<Certificate>,
+  /// Connector set by the user, or built from the settings above
+  pub(crate) connector: Option<Connector>,
+}
+
+impl Default for TlsSettings {
+  fn default() -> Self {
+    Self {
+      built_in_roots: true,
+      roots: vec![],
+      connector: None,
+    }
+  }
+}
+
+impl fmt::Debug for TlsSettings {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.debug_struct("TlsSettings")
+      .field("built_in_roots", &self.built_in_roots)
+      .field("roots", &self.roots.len())
+      .field("connector", &self.connector.as_ref().map(|_| ".."))
+      .finish()
+  }
+}
+
+impl TlsSettings {
+  /// Build the connector once so that reconnects reuse it, leaving it to
+  /// the backend's defaults when nothing was customised
+  pub(crate) fn resolve(mut self) -> Result<Self, KiteTickerError> {
+    if self.connector.is_none()
+      && (!self.built_in_roots || !self.roots.is_empty())
+    {
+      self.connector = Some(self.build_connector()?);
+    }
+    Ok(self)
+  }
+
+  #[cfg(feature = "native-tls")]
+  fn build_connector(&self) -> Result<Connector, KiteTickerError> {
+    let tls_error =
+      |e: native_tls::Error| KiteTickerError::Config(format!("TLS: {}", e));
+    let mut builder = native_tls::TlsConnector::builder();
+    builder.disable_built_in_roots(!self.built_in_roots);
+    for root in &self.roots {
+      builder.add_root_certificate(
+        native_tls::Certificate::from_der(&root.der).map_err(tls_error)?,
+      );
+    }
+    Ok(Connector::NativeTls(builder.build().map_err(tls_error)?))
+  }
+
+  #[cfg(all(feature = "__rustls-tls", not(feature = "native-tls")))]
+  fn build_connector(&self) -> Result<Connector, KiteTickerError> {
+    let mut store = rustls::RootCertStore::empty();
+    if self.built_in_roots {
+      #[cfg(feature = "rustls-tls-native-roots")]
+      {
+        let native = rustls_native_certs::load_native_certs().map_err(|e| {
+          KiteTickerError::Config(format!("failed to load native roots: {}", e))
+        })?;
+        let native = native.into_iter().map(|c| c.0).collect::<Vec<_>>();
+        store.add_parsable_certificates(&native);
+      }
+      #[cfg(feature = "rustls-tls-webpki-roots")]
+      store.add_trust_anchors(webpki_roots::TLS_SERVER_ROOTS.iter().map(
+        |ta| {
+          rustls::OwnedTrustAnchor::from_subject_spki_name_constraints(
+            ta.subject,
+            ta.spki,
+            ta.name_constraints,
+          )
+        },
+      ));
+    }
+    for root in &self.roots {
+      store
+        .add(&rustls::Certificate(root.der.clone()))
+        .map_err(|e| KiteTickerError::Config(format!("TLS: {}", e)))?;
+    }
+    let config = rustls::ClientConfig::builder()
+      .with_safe_defaults()
+      .with_root_certificates(store)
+      .with_no_client_auth();
+    Ok(Connector::Rustls(std::sync::Arc::new(config)))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::Certificate;
+
+  #[test]
+  fn test_pem() {
+    let pem = include_bytes!("../tests/fixtures/ca.pem");
+    let bundle = [&pem[..], &pem[..]].concat();
+    assert_eq!(Certificate::from_pem_bundle(&bundle).unwrap().len(), 2);
+    let certificate = Certificate::from_pem(pem).unwrap();
+    // DER encoded SEQUENCE
+    assert_eq!(certificate.der[0], 0x30);
+
+    assert!(Certificate::from_pem(b"").is_err());
+    assert!(Certificate::from_pem(
+      b"-----BEGIN CERTIFICATE-----\n!!\n-----END CERTIFICATE-----"
+    )
+    .is_err());
+  }
+}
@@ -0,0 +1,62 @@
+//! `tracing` spans and events, compiled out without the `tracing` feature
+//!
+//! Credentials never reach an event: only their redacting `Debug` output
+//! and the endpoint without its query are recorded.
+
+/// Emit an event at the given level, such as `event!(debug, ...)`
+macro_rules! event {
+  ($level:ident, $($arg:tt)+) => {
+    #[cfg(feature = "tracing")]
+    tracing::$level!($($arg)+);
+  };
+}
+pub(crate) use event;
+
+/// Run a future inside a span when the feature is enabled
+macro_rules! instrument {
+  ($future:expr, $($span:tt)+) => {{
+    #[cfg(feature = "tracing")]
+    let future =
+      tracing::Instrument::instrument($future, tracing::info_span!($($span)+));
+    #[cfg(not(feature = "tracing"))]
+    let future = $future;
+    future
+  }};
+}
+pub(crate) use instrument;
+
+/// Bytes shown around the offset of a decode error
+#[cfg(feature = "tracing")]
+const EXCERPT_LEN: usize = 32;
+
+/// Hex dump of the bytes of a frame starting at `offset`, where a packet
+/// failed to decode
+#[cfg(feature = "tracing")]
+pub(crate) fn hex_excerpt(input: &[u8], offset: usize) -> String {
+  let start = offset.min(input.len());
+  let end = (start + EXCERPT_LEN).min(input.len());
+  let mut excerpt = input[start..end]
+    .iter()
+    .map(|b| format!("{:02x}", b))
+    .collect::<Vec<_>>()
+    .join(" ");
+  if end < input.len() {
+    excerpt.push_str(" ..");
+  }
+  excerpt
+}
+
+#[cfg(all(test, feature = "tracing"))]
+mod tests {
+  use super::hex_excerpt;
+
+  #[test]
+  fn test_hex_excerpt() {
+    assert_eq!(hex_excerpt(&[0, 1, 0xab, 0xff], 2), "ab ff");
+    assert_eq!(hex_excerpt(&[0, 1], 5), "");
+    let long = (0..40).collect::<Vec<u8>>();
+    let excerpt = hex_excerpt(&long, 0);
+    assert!(excerpt.starts_with("00 01 02"));
+    assert!(excerpt.ends_with("1f .."));
+  }
+}
@@ -0,0 +1,151 @@
+use chrono::{DateTime, FixedOffset};
+use kiteticker_async::{
+  Depth, DepthItem, Exchange, FrameView, Mode, Price, Tick, OHLC,
+};
+use proptest::prelude::*;
+
+/// Raw field values of a packet, as they are on the wire
+#[derive(Debug, Clone)]
+struct Fields {
+  prices: [i32; 6],
+  values: [u32; 9],
+  depth: [(u32, i32, u16); 10],
+}
+
+/// Seconds since the epoch in IST, as the decoder gives them
+fn timestamp(secs: u32) -> DateTime<FixedOffset> {
+  let ist = FixedOffset::east_opt(5 * 3600 + 30 * 60).unwrap();
+  DateTime::from_timestamp(secs.into(), 0)
+    .unwrap()
+    .with_timezone(&ist)
+}
+
+fn mode() -> impl Strategy<Value = Mode> {
+  prop_oneof![Just(Mode::LTP), Just(Mode::Quote), Just(Mode::Full)]
+}
+
+fn fields() -> impl Strategy<Value = Fields> {
+  // halved so that the net change of an index fits in its field
+  let price = i32::MIN / 2..=i32::MAX / 2;
+  let value = 0..=i32::MAX as u32;
+  (
+    prop::array::uniform6(price.clone()),
+    prop::array::uniform9(value.clone()),
+    prop::array::uniform10((value, price, 0..=i16::MAX as u16)),
+  )
+    .prop_map(|(prices, values, depth)| Fields {
+      prices,
+      values,
+      depth,
+    })
+}
+
+/// Tick the decoder gives for a packet of `mode` holding `fields`
+fn tick(segment: u32, id: u32, mode: Mode, fields: &Fields) -> Tick {
+  let token = id << 8 | segment;
+  let exchange = Exchange::from(segment as usize);
+  let price = |units: i32| Price::new(units.into(), exchange.scale());
+  let [ltp, atp, open, high, low, close] = fields.prices.map(price);
+  let [ltq, volume, buy, sell, ltt, oi, oi_high, oi_low, ts] = fields.values;
+  let is_index = exchange == Exchange::INDICES;
+
+  let mut tick = Tick {
+    mode: mode.clone(),
+    instrument_token: token,
+    exchange: exchange.clone(),
+    is_tradable: !is_index,
+    is_index,
+    last_price: Some(ltp),
+    ..Tick::default()
+  };
+  if mode == Mode::LTP {
+    return tick;
+  }
+
+  tick.ohlc = Some(OHLC {
+    open,
+    high,
+    low,
+    close,
+  });
+  let net_change = (close != Price::default()).then(|| ltp - close);
+  if is_index {
+    tick.net_change = net_change;
+    if mode == Mode::Full {
+      tick.exchange_timestamp = Some(timestamp(ts));
+    }
+    return tick;
+  }
+
+  tick.last_traded_qty = Some(ltq);
+  tick.avg_traded_price = Some(atp);
+  tick.volume_traded = Some(volume);
+  tick.total_buy_qty = Some(buy);
+  tick.total_sell_qty = Some(sell);
+  if mode == Mode::Quote {
+    return tick;
+  }
+
+  tick.net_change = net_change;
+  tick.last_traded_timestamp = Some(timestamp(ltt));
+  tick.oi = Some(oi);
+  tick.oi_day_high = Some(oi_high);
+  tick.oi_day_low = Some(oi_low);
+  tick.exchange_timestamp = Some(timestamp(ts));
+  let item = |(qty, units, orders): (u32, i32, u16)| DepthItem {
+    qty,
+    price: price(units),
+    orders,
+  };
+  tick.depth = Some(Depth {
+    buy: std::array::from_fn(|i| item(fields.depth[i])),
+    sell: std::array::from_fn(|i| item(fields.depth[5 + i])),
+  });
+  tick
+}
+
+fn any_tick() -> impl Strategy<Value = Tick> {
+  (1..=9_u32, 0..1_u32 << 24, mode(), fields())
+    .prop_map(|(segment, id, mode, fields)| tick(segment, id, mode, &fields))
+}
+
+fn decode(packet: &[u8]) -> Tick {
+  Tick::try_from(packet).expect("encoded packet does not decode")
+}
+
+proptest! {
+  #[test]
+  fn test_round_trip(tick in any_tick()) {
+    let packet = tick.encode().unwrap();
+    let len = match (&tick.mode, tick.is_index) {
+      (Mode::LTP, _) => 8,
+      (Mode::Quote, true) => 28,
+      (Mode::Full, true) => 32,
+      (Mode::Quote, false) => 44,
+      (Mode::Full, false) => 184,
+    };
+    prop_assert_eq!(packet.len(), len);
+    prop_assert_eq!(decode(&packet), tick);
+  }
+
+  #[test]
+  fn test_every_mode_and_exchange(id in 0..1_u32 << 24, fields in fields()) {
+    for segment in 1..=9 {
+      for mode in [Mode::LTP, Mode::Quote, Mode::Full] {
+        let tick = tick(segment, id, mode, &fields);
+        prop_assert_eq!(decode(&tick.encode().unwrap()), tick);
+      }
+    }
+  }
+
+  #[test]
+  fn test_frame_round_trip(ticks in prop::collection::vec(any_tick(), 0..8)) {
+    let frame = Tick::encode_frame(&ticks).unwrap();
+    let decoded = FrameView::new(&frame)
+      .packets()
+      .map(|view| view.and_then(|v| v.to_tick()))
+      .collect::<Result<Vec<_>, _>>()
+      .unwrap();
+    prop_assert_eq!(decoded, ticks);
+  }
+}
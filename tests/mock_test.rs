@@ -0,0 +1,1337 @@
+use std::{
+  sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc, Mutex,
+  },
+  time::Duration,
+};
+
+use kiteticker_async::testing::{
+  encode_frame, encode_tick, MockKiteServer, MockProxy, MockRequest,
+};
+use kiteticker_async::*;
+use serde_json::json;
+
+const API_KEY: &str = "api_key";
+const ACCESS_TOKEN: &str = "access_token";
+
+async fn start() -> MockKiteServer {
+  MockKiteServer::start(API_KEY, ACCESS_TOKEN)
+    .await
+    .expect("failed to start mock server")
+}
+
+async fn connect(
+  server: &MockKiteServer,
+  policy: ReconnectPolicy,
+) -> KiteTickerAsync {
+  KiteTickerAsync::builder()
+    .endpoint(server.url())
+    .credentials(API_KEY, ACCESS_TOKEN)
+    .reconnect_policy(policy)
+    .connect()
+    .await
+    .expect("failed to create ticker")
+}
+
+fn fast_policy(max_retries: u32) -> ReconnectPolicy {
+  ReconnectPolicy {
+    max_retries: Some(max_retries),
+    initial_delay: Duration::from_millis(10),
+    max_delay: Duration::from_millis(50),
+    jitter: 0.0,
+    ..Default::default()
+  }
+}
+
+async fn next(sb: &mut KiteTickerSubscriber) -> Option<TickerMessage> {
+  tokio::time::timeout(Duration::from_secs(5), sb.next_message())
+    .await
+    .expect("timed out waiting for a message")
+    .expect("failed to read message")
+}
+
+/// Consume the `Connected` event every connection starts with
+async fn connected(sb: &mut KiteTickerSubscriber) {
+  match next(sb).await {
+    Some(TickerMessage::Connected { endpoint, .. }) => {
+      assert!(endpoint.starts_with("ws"))
+    }
+    m => panic!("unexpected message {:?}", m),
+  }
+}
+
+async fn recv_tick(receiver: &mut TickReceiver) -> Tick {
+  tokio::time::timeout(Duration::from_secs(5), receiver.recv())
+    .await
+    .expect("timed out waiting for a tick")
+    .expect("router closed")
+}
+
+fn price(value: f64) -> Price {
+  Price::from_f64(value, 2).unwrap()
+}
+
+fn tick(instrument_token: u32, last_price: f64) -> Tick {
+  Tick {
+    instrument_token,
+    last_price: Some(price(last_price)),
+    ..Default::default()
+  }
+}
+
+#[tokio::test]
+async fn test_mock_ticks() {
+  let server = start().await;
+  let ticker = connect(&server, ReconnectPolicy::disabled()).await;
+  let token = 408065;
+  let mut sb = ticker.subscribe(&[token], Some(Mode::LTP)).await.unwrap();
+  connected(&mut sb).await;
+  server.wait_for_mode(token, Mode::LTP).await;
+  assert_eq!(
+    server.requests(),
+    vec![
+      MockRequest::Subscribe(vec![token]),
+      MockRequest::Mode(Mode::LTP, vec![token])
+    ]
+  );
+
+  server
+    .publish(&[tick(token, 1573.15), tick(256265, 19000.0)])
+    .unwrap();
+  match next(&mut sb).await {
+    Some(TickerMessage::Ticks(xs)) => {
+      assert_eq!(xs.len(), 1);
+      assert_eq!(xs[0].instrument_token, token);
+      assert_eq!(xs[0].content.mode, Mode::LTP);
+      assert_eq!(xs[0].content.last_price, Some(price(1573.15)));
+    }
+    m => panic!("unexpected message {:?}", m),
+  }
+
+  // a price finer than the paisa of the segment does not fit, and nothing
+  // of the batch is sent
+  let fine = Tick {
+    last_price: Some(Price::new(157315, 3)),
+    ..tick(token, 0.0)
+  };
+  assert_eq!(
+    server.publish(&[tick(token, 1573.2), fine.clone()]),
+    Err(EncodeError {
+      field: "last_price",
+      reason: EncodeReason::Precision
+    })
+  );
+  assert!(encode_tick(&fine, &Mode::LTP).is_err());
+
+  sb.set_mode(&[token], Mode::Full).await.unwrap();
+  server.wait_for_mode(token, Mode::Full).await;
+  server.publish(&[tick(token, 1574.0)]).unwrap();
+  match next(&mut sb).await {
+    Some(TickerMessage::Ticks(xs)) => {
+      assert_eq!(xs[0].content.mode, Mode::Full);
+      assert!(xs[0].content.depth.is_some());
+    }
+    m => panic!("unexpected message {:?}", m),
+  }
+
+  sb.close().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_mock_text_messages() {
+  let server = start().await;
+  let ticker = connect(&server, ReconnectPolicy::disabled()).await;
+  let mut sb = ticker.subscribe(&[408065], None).await.unwrap();
+  connected(&mut sb).await;
+  server.wait_for_subscription(408065).await;
+
+  server.send_error("invalid token");
+  assert!(matches!(next(&mut sb).await, Some(TickerMessage::Error(_))));
+
+  server.send_message(json!("market closes early today"));
+  assert!(matches!(
+    next(&mut sb).await,
+    Some(TickerMessage::Message(_))
+  ));
+
+  server.send_order_postback(json!({ "order_id": "220303000308932" }));
+  assert!(matches!(
+    next(&mut sb).await,
+    Some(TickerMessage::OrderPostback(Err(_)))
+  ));
+
+  server.close_connections(1000, "bye");
+  match next(&mut sb).await {
+    Some(TickerMessage::ClosingMessage(v)) => {
+      assert_eq!(v["reason"], "bye");
+    }
+    m => panic!("unexpected message {:?}", m),
+  }
+  match next(&mut sb).await {
+    Some(TickerMessage::Disconnected {
+      reason: DisconnectReason::Closed { code, reason },
+    }) => {
+      assert_eq!((code, reason.as_str()), (1000, "bye"));
+    }
+    m => panic!("unexpected message {:?}", m),
+  }
+  assert!(next(&mut sb).await.is_none());
+}
+
+#[tokio::test]
+async fn test_mock_auth_failure() {
+  let server = start().await;
+  let ticker = KiteTickerAsync::builder()
+    .endpoint(server.url())
+    .credentials(API_KEY, "expired")
+    .connect()
+    .await;
+  match ticker.unwrap_err() {
+    KiteTickerError::Handshake { status, body } => {
+      assert_eq!(status, 403);
+      assert!(body.unwrap().contains("TokenException"));
+    }
+    e => panic!("unexpected error {:?}", e),
+  }
+  assert_eq!(server.accepted_connections(), 0);
+}
+
+#[tokio::test]
+async fn test_mock_reconnect() {
+  let server = start().await;
+  let ticker = connect(&server, fast_policy(5)).await;
+  let mut sb = ticker.subscribe(&[408065], Some(Mode::Full)).await.unwrap();
+  connected(&mut sb).await;
+  sb.subscribe(&[256265], Some(Mode::LTP)).await.unwrap();
+  server.wait_for_mode(256265, Mode::LTP).await;
+
+  server.fail_handshakes(1);
+  server.drop_connections();
+  assert!(matches!(
+    next(&mut sb).await,
+    Some(TickerMessage::Disconnected {
+      reason: DisconnectReason::Lost(_)
+    })
+  ));
+  assert!(matches!(
+    next(&mut sb).await,
+    Some(TickerMessage::Reconnecting {
+      attempt: 1,
+      last_error: None,
+      ..
+    })
+  ));
+  assert!(matches!(
+    next(&mut sb).await,
+    Some(TickerMessage::Reconnecting {
+      attempt: 2,
+      last_error: Some(KiteTickerError::Handshake { status: 503, .. }),
+      ..
+    })
+  ));
+  connected(&mut sb).await;
+  match next(&mut sb).await {
+    Some(TickerMessage::Resubscribed { mut tokens }) => {
+      tokens.sort();
+      assert_eq!(tokens, vec![256265, 408065]);
+    }
+    m => panic!("unexpected message {:?}", m),
+  }
+
+  server.wait_for_mode(408065, Mode::Full).await;
+  server.wait_for_mode(256265, Mode::LTP).await;
+  assert_eq!(server.accepted_connections(), 2);
+
+  server.publish(&[tick(408065, 1573.15)]).unwrap();
+  assert!(matches!(next(&mut sb).await, Some(TickerMessage::Ticks(_))));
+  sb.close().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_mock_gave_up() {
+  let server = start().await;
+  let ticker = connect(&server, fast_policy(2)).await;
+  let mut sb = ticker.subscribe(&[408065], None).await.unwrap();
+  connected(&mut sb).await;
+  server.wait_for_subscription(408065).await;
+
+  server.fail_handshakes(usize::MAX);
+  server.drop_connections();
+  assert!(matches!(
+    next(&mut sb).await,
+    Some(TickerMessage::Disconnected { .. })
+  ));
+  for attempt in 1..=2 {
+    match next(&mut sb).await {
+      Some(TickerMessage::Reconnecting { attempt: a, .. }) => {
+        assert_eq!(a, attempt)
+      }
+      m => panic!("unexpected message {:?}", m),
+    }
+  }
+  assert!(matches!(
+    next(&mut sb).await,
+    Some(TickerMessage::GaveUp {
+      attempts: 2,
+      last_error: Some(KiteTickerError::Handshake { status: 503, .. })
+    })
+  ));
+  assert!(next(&mut sb).await.is_none());
+}
+
+#[tokio::test]
+async fn test_mock_reconnect_rejected() {
+  let server = start().await;
+  let ticker = connect(&server, fast_policy(5)).await;
+  let mut sb = ticker.subscribe(&[408065], None).await.unwrap();
+  connected(&mut sb).await;
+  server.wait_for_subscription(408065).await;
+
+  // an expired token is reported instead of retried until the policy gives
+  // up
+  server.reject_handshakes(usize::MAX);
+  server.drop_connections();
+  assert!(matches!(
+    next(&mut sb).await,
+    Some(TickerMessage::Disconnected { .. })
+  ));
+  assert!(matches!(
+    next(&mut sb).await,
+    Some(TickerMessage::Reconnecting { attempt: 1, .. })
+  ));
+  match sb.next_message().await {
+    Err(KiteTickerError::Handshake { status: 403, body }) => {
+      assert!(body.unwrap().contains("TokenException"))
+    }
+    m => panic!("unexpected message {:?}", m),
+  }
+  assert!(next(&mut sb).await.is_none());
+  assert_eq!(server.accepted_connections(), 1);
+}
+
+#[tokio::test]
+async fn test_mock_handle_while_reading() {
+  let server = start().await;
+  let ticker = connect(&server, ReconnectPolicy::disabled()).await;
+  let mut sb = ticker.subscribe(&[408065], Some(Mode::LTP)).await.unwrap();
+  connected(&mut sb).await;
+  let handle = sb.handle();
+  let other = handle.clone();
+
+  let reader = tokio::spawn(async move {
+    let message = next(&mut sb).await;
+    (sb, message)
+  });
+
+  tokio::time::timeout(Duration::from_secs(1), async {
+    handle.subscribe(&[256265], Some(Mode::Full)).await.unwrap();
+    handle.set_mode(&[408065], Mode::Quote).await.unwrap();
+    other.unsubscribe(&[256265]).await.unwrap();
+  })
+  .await
+  .expect("commands blocked by the pending read");
+
+  assert_eq!(other.get_subscribed(), vec![408065]);
+  assert_eq!(handle.subscriptions()[&408065], Mode::Quote);
+  server.wait_for_mode(408065, Mode::Quote).await;
+  assert!(!server.subscriptions().contains_key(&256265));
+
+  server.publish(&[tick(408065, 1573.15)]).unwrap();
+  let (mut sb, message) = reader.await.unwrap();
+  assert!(matches!(message, Some(TickerMessage::Ticks(_))));
+  assert_eq!(sb.get_subscribed(), vec![408065]);
+  sb.close().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_mock_stale_connection() {
+  let server = start().await;
+  let ticker = KiteTickerAsync::builder()
+    .endpoint(server.url())
+    .credentials(API_KEY, ACCESS_TOKEN)
+    .reconnect_policy(fast_policy(5))
+    .read_timeout(Duration::from_millis(200))
+    .connect()
+    .await
+    .unwrap();
+  let mut sb = ticker.subscribe(&[408065], None).await.unwrap();
+  connected(&mut sb).await;
+  server.wait_for_subscription(408065).await;
+
+  server.heartbeat();
+  assert!(matches!(
+    next(&mut sb).await,
+    Some(TickerMessage::Heartbeat)
+  ));
+
+  match next(&mut sb).await {
+    Some(TickerMessage::Stale { idle }) => {
+      assert!(idle >= Duration::from_millis(200))
+    }
+    m => panic!("unexpected message {:?}", m),
+  }
+  assert!(matches!(
+    next(&mut sb).await,
+    Some(TickerMessage::Disconnected {
+      reason: DisconnectReason::Stale { .. }
+    })
+  ));
+  assert!(matches!(
+    next(&mut sb).await,
+    Some(TickerMessage::Reconnecting { attempt: 1, .. })
+  ));
+  connected(&mut sb).await;
+  assert!(matches!(
+    next(&mut sb).await,
+    Some(TickerMessage::Resubscribed { .. })
+  ));
+  assert_eq!(server.accepted_connections(), 2);
+  sb.close().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_mock_ping_pong() {
+  let server = start().await;
+  let ticker = connect(&server, ReconnectPolicy::disabled()).await;
+  let mut sb = ticker.subscribe(&[408065], None).await.unwrap();
+  connected(&mut sb).await;
+  server.wait_for_subscription(408065).await;
+  assert_eq!(sb.handle().last_rtt(), None);
+
+  let rtt = tokio::time::timeout(Duration::from_secs(5), sb.ping())
+    .await
+    .unwrap()
+    .unwrap();
+  assert_eq!(sb.handle().last_rtt(), Some(rtt));
+
+  server.send_ping();
+  tokio::time::timeout(Duration::from_secs(5), server.wait_for_pongs(1))
+    .await
+    .expect("no pong for the server ping");
+
+  server.heartbeat();
+  assert!(matches!(
+    next(&mut sb).await,
+    Some(TickerMessage::Heartbeat)
+  ));
+  sb.close().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_mock_ping_timeout() {
+  let server = start().await;
+  let timeout = Duration::from_millis(200);
+  let ticker = KiteTickerAsync::builder()
+    .endpoint(server.url())
+    .credentials(API_KEY, ACCESS_TOKEN)
+    .reconnect_policy(ReconnectPolicy::disabled())
+    .ping_timeout(timeout)
+    .connect()
+    .await
+    .unwrap();
+  let mut sb = ticker.subscribe(&[408065], None).await.unwrap();
+  connected(&mut sb).await;
+  server.wait_for_subscription(408065).await;
+
+  // the pong never arrives while the server is not reading
+  server.pause_reading(true);
+  let ping = tokio::time::timeout(Duration::from_secs(5), sb.ping())
+    .await
+    .expect("ping did not time out");
+  assert!(matches!(ping, Err(KiteTickerError::Timeout(t)) if t == timeout));
+  assert_eq!(sb.handle().last_rtt(), None);
+
+  server.pause_reading(false);
+  let rtt = tokio::time::timeout(Duration::from_secs(5), sb.ping())
+    .await
+    .unwrap()
+    .unwrap();
+  assert_eq!(sb.handle().last_rtt(), Some(rtt));
+  sb.close().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_mock_pool() {
+  let server = start().await;
+  let builder = KiteTickerAsync::builder()
+    .endpoint(server.url())
+    .credentials(API_KEY, ACCESS_TOKEN)
+    .reconnect_policy(ReconnectPolicy::disabled());
+  let limits = PoolLimits {
+    max_tokens_per_connection: 2,
+    max_connections: 3,
+  };
+  let mut pool = KiteTickerPool::new(builder, limits);
+
+  pool.subscribe(&[1, 2, 3], Some(Mode::LTP)).await.unwrap();
+  pool.subscribe(&[4, 5], Some(Mode::Full)).await.unwrap();
+  server.wait_for_spread(3, 5).await;
+  let shards = pool.shards();
+  assert_eq!(shards.len(), 3);
+  assert!(shards.iter().all(|s| s.tokens <= 2));
+  assert!(shards.iter().all(|s| s.state == ConnectionState::Connected));
+  assert!(pool.subscribe(&[6, 7], None).await.is_err());
+
+  server.publish(&[tick(4, 1573.15)]).unwrap();
+  let message = tokio::time::timeout(Duration::from_secs(5), async {
+    loop {
+      let message = pool.next_message().await.unwrap();
+      if !matches!(message.message, Ok(TickerMessage::Connected { .. })) {
+        break message;
+      }
+    }
+  })
+  .await
+  .unwrap();
+  // 1 and 2 fill the first connection, 3 and 4 share the second
+  assert_eq!(message.shard, 1);
+  match message.message {
+    Ok(TickerMessage::Ticks(xs)) => assert_eq!(xs[0].instrument_token, 4),
+    m => panic!("unexpected message {:?}", m),
+  }
+
+  pool.unsubscribe(&[1, 3]).await.unwrap();
+  server.wait_for_spread(2, 3).await;
+  assert_eq!(pool.shards().len(), 2);
+  let mut tokens = pool.get_subscribed();
+  tokens.sort();
+  assert_eq!(tokens, vec![2, 4, 5]);
+  let modes = server
+    .all_subscriptions()
+    .into_iter()
+    .flatten()
+    .collect::<std::collections::HashMap<_, _>>();
+  assert_eq!(modes[&2], Mode::LTP);
+  assert_eq!(modes[&4], Mode::Full);
+
+  pool.unsubscribe(&[]).await.unwrap();
+  server.wait_for_spread(0, 0).await;
+  assert!(pool.shards().is_empty());
+}
+
+#[tokio::test]
+async fn test_mock_fatal_close() {
+  let server = start().await;
+  let ticker = connect(&server, fast_policy(5)).await;
+  let mut sb = ticker.subscribe(&[408065], None).await.unwrap();
+  connected(&mut sb).await;
+  server.wait_for_subscription(408065).await;
+
+  server.close_connections(1008, "policy violation");
+  assert!(matches!(
+    next(&mut sb).await,
+    Some(TickerMessage::ClosingMessage(_))
+  ));
+  assert!(matches!(
+    next(&mut sb).await,
+    Some(TickerMessage::Disconnected {
+      reason: DisconnectReason::Closed { code: 1008, .. }
+    })
+  ));
+  assert!(next(&mut sb).await.is_none());
+  assert_eq!(server.accepted_connections(), 1);
+  assert!(matches!(
+    sb.subscribe(&[256265], None).await,
+    Err(KiteTickerError::TickerClosed)
+  ));
+}
+
+#[tokio::test]
+async fn test_mock_damaged_frame() {
+  let server = start().await;
+  let ticker = connect(&server, ReconnectPolicy::disabled()).await;
+  let mut sb = ticker.subscribe(&[408065], None).await.unwrap();
+  connected(&mut sb).await;
+  server.wait_for_subscription(408065).await;
+
+  let good = encode_tick(&tick(408065, 1573.15), &Mode::Quote).unwrap();
+  let mut frame = encode_frame(&[good.clone(), vec![0; 5], good]);
+  server.send_binary(frame.clone());
+  match next(&mut sb).await {
+    Some(TickerMessage::Ticks(xs)) => assert_eq!(xs.len(), 2),
+    m => panic!("unexpected message {:?}", m),
+  }
+  match sb.next_message().await {
+    Err(KiteTickerError::Decode(e)) => {
+      assert_eq!(e.offset, 2 + 2 + 44 + 2);
+      assert!(matches!(
+        e.reason,
+        DecodeReason::Truncated { needed: 8, .. }
+      ));
+    }
+    m => panic!("unexpected message {:?}", m),
+  }
+
+  // the length prefix of the last packet claims more than what is left
+  frame.truncate(frame.len() - 10);
+  server.send_binary(frame);
+  match next(&mut sb).await {
+    Some(TickerMessage::Ticks(xs)) => assert_eq!(xs.len(), 1),
+    m => panic!("unexpected message {:?}", m),
+  }
+  assert!(matches!(
+    sb.next_message().await,
+    Err(KiteTickerError::Decode(_))
+  ));
+  match sb.next_message().await {
+    Err(KiteTickerError::Decode(e)) => {
+      assert_eq!(
+        e.reason,
+        DecodeReason::Truncated {
+          needed: 44,
+          available: 34
+        }
+      );
+    }
+    m => panic!("unexpected message {:?}", m),
+  }
+
+  server.heartbeat();
+  assert!(matches!(
+    next(&mut sb).await,
+    Some(TickerMessage::Heartbeat)
+  ));
+  sb.close().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_mock_streams() {
+  use futures_util::StreamExt;
+
+  let server = start().await;
+  let ticker = connect(&server, ReconnectPolicy::disabled()).await;
+  let mut sb = ticker.subscribe(&[408065, 256265], None).await.unwrap();
+  connected(&mut sb).await;
+  server.wait_for_subscription(256265).await;
+
+  server.heartbeat();
+  assert!(matches!(
+    sb.next().await,
+    Some(Ok(TickerMessage::Heartbeat))
+  ));
+
+  let handle = sb.handle();
+  let mut ticks = Box::pin(sb.ticks());
+  server.heartbeat();
+  server.send_error("invalid token");
+  server
+    .publish(&[tick(408065, 1573.15), tick(256265, 19000.0)])
+    .unwrap();
+  let tokens = tokio::time::timeout(Duration::from_secs(5), async {
+    vec![
+      ticks.next().await.unwrap().instrument_token,
+      ticks.next().await.unwrap().instrument_token,
+    ]
+  })
+  .await
+  .unwrap();
+  assert_eq!(tokens, vec![408065, 256265]);
+
+  handle.close().await.unwrap();
+  let end = tokio::time::timeout(Duration::from_secs(5), ticks.next()).await;
+  assert!(end.unwrap().is_none());
+}
+
+#[tokio::test]
+async fn test_mock_broadcast() {
+  let server = start().await;
+  let ticker = connect(&server, ReconnectPolicy::disabled()).await;
+  let mut sb = ticker.subscribe(&[408065], None).await.unwrap();
+  connected(&mut sb).await;
+  server.wait_for_subscription(408065).await;
+
+  let broadcast = KiteTickerBroadcast::new(sb, 16);
+  let mut fast = broadcast.receiver();
+  let mut slow = broadcast.receiver_with_capacity(2);
+  assert_eq!(broadcast.receiver_count(), 2);
+
+  for i in 0..5 {
+    server.publish(&[tick(408065, 1573.0 + i as f64)]).unwrap();
+  }
+  for _ in 0..5 {
+    let message = tokio::time::timeout(Duration::from_secs(5), fast.recv())
+      .await
+      .unwrap();
+    assert!(matches!(message, Some(Ok(TickerMessage::Ticks(_)))));
+  }
+
+  // the gap is reported after the messages buffered before it, and before
+  // the ones sent after it
+  for last_price in [1573.0, 1574.0] {
+    match slow.recv().await {
+      Some(Ok(TickerMessage::Ticks(xs))) => {
+        assert_eq!(xs[0].content.last_price, Some(price(last_price)))
+      }
+      m => panic!("unexpected message {:?}", m),
+    }
+  }
+  server.publish(&[tick(408065, 1578.0)]).unwrap();
+  assert!(matches!(
+    slow.recv().await,
+    Some(Err(KiteTickerError::Lagged(3)))
+  ));
+  match slow.recv().await {
+    Some(Ok(TickerMessage::Ticks(xs))) => {
+      assert_eq!(xs[0].content.last_price, Some(price(1578.0)))
+    }
+    m => panic!("unexpected message {:?}", m),
+  }
+  assert_eq!(slow.dropped(), 3);
+  assert_eq!(fast.dropped(), 0);
+
+  drop(slow);
+  assert_eq!(broadcast.receiver_count(), 1);
+  broadcast.handle().close().await.unwrap();
+  let end = tokio::time::timeout(Duration::from_secs(5), async {
+    while let Some(Ok(_)) = fast.recv().await {}
+  })
+  .await;
+  assert!(end.is_ok());
+}
+
+#[tokio::test]
+async fn test_mock_router() {
+  let server = start().await;
+  let ticker = connect(&server, ReconnectPolicy::disabled()).await;
+  let sb = ticker.subscribe(&[], None).await.unwrap();
+  let router = KiteTickerRouter::new(sb, 16);
+
+  let mut infy = router.route(&[408065], Some(Mode::LTP)).await.unwrap();
+  let mut both = router
+    .route(&[408065, 256265], Some(Mode::Full))
+    .await
+    .unwrap();
+  server.wait_for_mode(256265, Mode::Full).await;
+  assert_eq!(server.subscriptions()[&408065], Mode::LTP);
+
+  server
+    .publish(&[tick(408065, 1573.15), tick(256265, 19000.0)])
+    .unwrap();
+  assert_eq!(recv_tick(&mut infy).await.instrument_token, 408065);
+  assert_eq!(recv_tick(&mut both).await.instrument_token, 408065);
+  assert_eq!(recv_tick(&mut both).await.instrument_token, 256265);
+
+  drop(infy);
+  let mut tokens = router.routed_tokens();
+  tokens.sort();
+  assert_eq!(tokens, vec![256265, 408065]);
+  server.publish(&[tick(408065, 1574.0)]).unwrap();
+  assert_eq!(recv_tick(&mut both).await.last_price, Some(price(1574.0)));
+
+  drop(both);
+  server.wait_for_unsubscription(408065).await;
+  server.wait_for_unsubscription(256265).await;
+  assert!(router.routed_tokens().is_empty());
+  assert!(router.handle().get_subscribed().is_empty());
+  router.handle().close().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_mock_router_subscriptions() {
+  let server = start().await;
+  let ticker = connect(&server, ReconnectPolicy::disabled()).await;
+  let sb = ticker.subscribe(&[408065], Some(Mode::LTP)).await.unwrap();
+  server.wait_for_mode(408065, Mode::LTP).await;
+  let handle = sb.handle();
+  let router = KiteTickerRouter::new(sb, 16);
+
+  // tokens subscribed before the router keep their mode and subscription
+  let both = router
+    .route(&[408065, 256265], Some(Mode::Full))
+    .await
+    .unwrap();
+  server.wait_for_mode(256265, Mode::Full).await;
+  assert_eq!(server.subscriptions()[&408065], Mode::LTP);
+  drop(both);
+  server.wait_for_unsubscription(256265).await;
+  assert_eq!(handle.get_subscribed(), vec![408065]);
+  assert!(router.routed_tokens().is_empty());
+
+  // and so do tokens subscribed directly once the router exists
+  handle.subscribe(&[738561], Some(Mode::LTP)).await.unwrap();
+  let reliance = router.route(&[738561, 256265], None).await.unwrap();
+  server.wait_for_subscription(256265).await;
+  drop(reliance);
+  server.wait_for_unsubscription(256265).await;
+  let mut subscribed = handle.get_subscribed();
+  subscribed.sort();
+  assert_eq!(subscribed, vec![408065, 738561]);
+  assert_eq!(server.subscriptions()[&738561], Mode::LTP);
+
+  // a token routed again right after its last route is dropped is
+  // subscribed again, after the pending unsubscribe
+  let nifty = router.route(&[256265], None).await.unwrap();
+  drop(nifty);
+  let _nifty = router.route(&[256265], None).await.unwrap();
+  server.wait_for_subscription(256265).await;
+  assert!(handle.get_subscribed().contains(&256265));
+
+  // the second route waits on the subscribe of the first, which fails as
+  // the connection is closed before it is sent
+  let messages = futures_util::stream::pending();
+  let router = KiteTickerRouter::from_stream(handle.clone(), messages, 16);
+  let (closed, first, second) = tokio::join!(
+    handle.close(),
+    router.route(&[884737], None),
+    router.route(&[884737], None),
+  );
+  closed.unwrap();
+  assert!(matches!(first, Err(KiteTickerError::Transport(_))));
+  assert!(matches!(second, Err(KiteTickerError::Transport(_))));
+  assert!(router.routed_tokens().is_empty());
+}
+
+#[tokio::test]
+async fn test_mock_conflation() {
+  let server = start().await;
+  let ticker = connect(&server, ReconnectPolicy::disabled()).await;
+  let sb = ticker.subscribe(&[408065, 256265], None).await.unwrap();
+  let handle = sb.handle();
+  server.wait_for_subscription(256265).await;
+  let mut conflator = KiteTickerConflator::new(sb);
+
+  for price in [1573.0, 1574.0, 1575.0] {
+    server.publish(&[tick(408065, price)]).unwrap();
+  }
+  server.publish(&[tick(256265, 19000.0)]).unwrap();
+  tokio::time::timeout(Duration::from_secs(5), async {
+    while conflator.dropped().get(&408065) != Some(&2)
+      || conflator.dropped().len() != 1
+    {
+      tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+  })
+  .await
+  .expect("ticks were not conflated");
+
+  let snapshot = conflator.next_snapshot().await.unwrap();
+  assert_eq!(snapshot.len(), 2);
+  assert_eq!(snapshot[&408065].last_price, Some(price(1575.0)));
+  assert_eq!(snapshot[&256265].last_price, Some(price(19000.0)));
+
+  server.publish(&[tick(408065, 1576.0)]).unwrap();
+  let snapshot =
+    tokio::time::timeout(Duration::from_secs(5), conflator.next_snapshot())
+      .await
+      .unwrap()
+      .unwrap();
+  assert_eq!(snapshot.len(), 1);
+  assert_eq!(snapshot[&408065].last_price, Some(price(1576.0)));
+
+  let mut conflator = conflator.sample_interval(Duration::from_millis(200));
+  server.publish(&[tick(408065, 1577.0)]).unwrap();
+  conflator.next_snapshot().await.unwrap();
+  let started = std::time::Instant::now();
+  server.publish(&[tick(408065, 1578.0)]).unwrap();
+  let snapshot = conflator.next_snapshot().await.unwrap();
+  assert!(started.elapsed() >= Duration::from_millis(150));
+  assert_eq!(snapshot[&408065].last_price, Some(price(1578.0)));
+
+  handle.close().await.unwrap();
+  let end =
+    tokio::time::timeout(Duration::from_secs(5), conflator.next_snapshot());
+  assert!(end.await.unwrap().is_none());
+}
+
+#[tokio::test]
+async fn test_mock_proxy() {
+  let server = start().await;
+  let target = server.url().trim_start_matches("ws://").to_string();
+  let http = MockProxy::start_http(Some(("user", "secret")))
+    .await
+    .unwrap();
+  let socks5 = MockProxy::start_socks5(Some(("user", "secret")))
+    .await
+    .unwrap();
+
+  for (proxy, mock) in [
+    (Proxy::http("127.0.0.1", http.port()), &http),
+    (Proxy::socks5("127.0.0.1", socks5.port()), &socks5),
+  ] {
+    let ticker = KiteTickerAsync::builder()
+      .endpoint(server.url())
+      .credentials(API_KEY, ACCESS_TOKEN)
+      .proxy(proxy.clone().auth("user", "secret"))
+      .connect()
+      .await
+      .unwrap();
+    let mut sb = ticker.subscribe(&[408065], Some(Mode::LTP)).await.unwrap();
+    connected(&mut sb).await;
+    server.wait_for_mode(408065, Mode::LTP).await;
+    server.publish(&[tick(408065, 1573.15)]).unwrap();
+    match next(&mut sb).await {
+      Some(TickerMessage::Ticks(xs)) => {
+        assert_eq!(xs[0].instrument_token, 408065)
+      }
+      m => panic!("unexpected message {:?}", m),
+    }
+    assert_eq!(mock.targets(), vec![target.clone()]);
+    sb.close().await.unwrap();
+
+    let rejected = KiteTickerAsync::builder()
+      .endpoint(server.url())
+      .credentials(API_KEY, ACCESS_TOKEN)
+      .proxy(proxy.auth("user", "wrong"))
+      .connect()
+      .await;
+    assert!(matches!(rejected, Err(KiteTickerError::Proxy(_))));
+  }
+}
+
+/// Terminate TLS with the certificate in `tests/fixtures` in front of the
+/// mock server, returning the port to connect to
+async fn tls_front(server: &MockKiteServer) -> u16 {
+  use tokio_native_tls::{native_tls, TlsAcceptor};
+
+  let identity = native_tls::Identity::from_pkcs8(
+    include_bytes!("fixtures/localhost.pem"),
+    include_bytes!("fixtures/localhost.key"),
+  )
+  .unwrap();
+  let acceptor =
+    TlsAcceptor::from(native_tls::TlsAcceptor::new(identity).unwrap());
+  let listener = tokio::net::TcpListener::bind(("127.0.0.1", 0))
+    .await
+    .unwrap();
+  let port = listener.local_addr().unwrap().port();
+  let upstream = server.url().trim_start_matches("ws://").to_string();
+  tokio::spawn(async move {
+    while let Ok((stream, _)) = listener.accept().await {
+      let acceptor = acceptor.clone();
+      let upstream = upstream.clone();
+      tokio::spawn(async move {
+        let Ok(mut client) = acceptor.accept(stream).await else {
+          return;
+        };
+        let mut server =
+          tokio::net::TcpStream::connect(upstream).await.unwrap();
+        let _ = tokio::io::copy_bidirectional(&mut client, &mut server).await;
+      });
+    }
+  });
+  port
+}
+
+#[tokio::test]
+async fn test_mock_tls() {
+  let server = start().await;
+  let endpoint = format!("wss://localhost:{}", tls_front(&server).await);
+
+  let untrusted = KiteTickerAsync::builder()
+    .endpoint(&endpoint)
+    .credentials(API_KEY, ACCESS_TOKEN)
+    .connect()
+    .await;
+  assert!(matches!(untrusted, Err(KiteTickerError::Transport(_))));
+
+  let ca = Certificate::from_pem(include_bytes!("fixtures/ca.pem")).unwrap();
+  let ticker = KiteTickerAsync::builder()
+    .endpoint(&endpoint)
+    .credentials(API_KEY, ACCESS_TOKEN)
+    .add_root_certificate(ca)
+    .tls_built_in_root_certs(false)
+    .connect()
+    .await
+    .unwrap();
+  let mut sb = ticker.subscribe(&[408065], Some(Mode::LTP)).await.unwrap();
+  connected(&mut sb).await;
+  server.wait_for_mode(408065, Mode::LTP).await;
+  server.publish(&[tick(408065, 1573.15)]).unwrap();
+  match next(&mut sb).await {
+    Some(TickerMessage::Ticks(xs)) => {
+      assert_eq!(xs[0].instrument_token, 408065)
+    }
+    m => panic!("unexpected message {:?}", m),
+  }
+}
+
+#[tokio::test]
+async fn test_mock_shutdown() {
+  let server = start().await;
+  let ticker = connect(&server, fast_policy(5)).await;
+  let handle = ticker.handle();
+  let mut sb = ticker.subscribe(&[408065, 256265], None).await.unwrap();
+  connected(&mut sb).await;
+  server.wait_for_subscription(256265).await;
+
+  let cancel = CancellationToken::new();
+  let shutdown = tokio::spawn({
+    let handle = handle.clone();
+    let cancel = cancel.clone();
+    async move { handle.shutdown_on(cancel, ShutdownOptions::default()).await }
+  });
+  let (ticks, mut received) = tokio::sync::mpsc::unbounded_channel();
+  let consumer = tokio::spawn(async move {
+    while let Some(message) = sb.next_message().await.unwrap() {
+      if let TickerMessage::Ticks(xs) = message {
+        xs.into_iter().for_each(|x| ticks.send(x).unwrap());
+      }
+    }
+  });
+
+  server.publish(&[tick(408065, 1573.15)]).unwrap();
+  server.publish(&[tick(256265, 19000.0)]).unwrap();
+  for _ in 0..2 {
+    received.recv().await.unwrap();
+  }
+  cancel.cancel();
+  let summary = shutdown.await.unwrap().unwrap();
+  assert_eq!(summary.unsubscribed, vec![256265, 408065]);
+  assert!(summary.close_acknowledged);
+  assert_eq!(summary.dropped, 0);
+  tokio::time::timeout(Duration::from_secs(5), consumer)
+    .await
+    .expect("consumer did not end")
+    .unwrap();
+  assert_eq!(
+    server.requests().last(),
+    Some(&MockRequest::Unsubscribe(vec![256265, 408065]))
+  );
+  assert_eq!(handle.state(), ConnectionState::Closed);
+  assert!(matches!(
+    handle.shutdown(ShutdownOptions::default()).await,
+    Err(KiteTickerError::TickerClosed)
+  ));
+}
+
+#[tokio::test]
+async fn test_mock_close_unanswered() {
+  let server = start().await;
+  let ticker = connect(&server, ReconnectPolicy::disabled()).await;
+  let handle = ticker.handle();
+  let mut sb = ticker.subscribe(&[408065], None).await.unwrap();
+  connected(&mut sb).await;
+  server.wait_for_subscription(408065).await;
+
+  // the server never reads the close frame, and no read timeout is set
+  server.pause_reading(true);
+  let summary = tokio::time::timeout(
+    Duration::from_secs(5),
+    handle.shutdown(ShutdownOptions {
+      close_timeout: Duration::from_millis(100),
+      ..Default::default()
+    }),
+  )
+  .await
+  .expect("shutdown did not end")
+  .unwrap();
+  assert!(!summary.close_acknowledged);
+
+  let ticker = connect(&server, ReconnectPolicy::disabled()).await;
+  let mut sb = ticker.subscribe(&[408065], None).await.unwrap();
+  connected(&mut sb).await;
+  server.wait_for_subscription(408065).await;
+  server.pause_reading(true);
+  // nothing but the close timeout is left to wait for
+  tokio::time::pause();
+  sb.close().await.unwrap();
+  let end = tokio::time::timeout(Duration::from_secs(10), async {
+    while let Ok(Some(_)) = sb.next_message().await {}
+  })
+  .await;
+  assert!(end.is_ok(), "close did not end the stream");
+}
+
+#[tokio::test]
+async fn test_mock_shutdown_slow_consumer() {
+  let server = start().await;
+  let ticker = KiteTickerAsync::builder()
+    .endpoint(server.url())
+    .credentials(API_KEY, ACCESS_TOKEN)
+    .message_buffer(1)
+    .connect()
+    .await
+    .unwrap();
+  let mut sb = ticker.subscribe(&[408065], None).await.unwrap();
+  connected(&mut sb).await;
+  server.wait_for_subscription(408065).await;
+
+  for price in [1.0, 2.0, 3.0] {
+    server.publish(&[tick(408065, price)]).unwrap();
+  }
+  assert!(matches!(next(&mut sb).await, Some(TickerMessage::Ticks(_))));
+  // the second tick waits in the channel, the third one in the worker
+  tokio::time::sleep(Duration::from_millis(100)).await;
+
+  let summary = sb
+    .handle()
+    .shutdown(ShutdownOptions {
+      drain_timeout: Duration::from_millis(50),
+      ..Default::default()
+    })
+    .await
+    .unwrap();
+  // the third tick and the disconnection event
+  assert_eq!(summary.drained, 0);
+  assert_eq!(summary.dropped, 2);
+  match next(&mut sb).await {
+    Some(TickerMessage::Ticks(xs)) => {
+      assert_eq!(xs[0].content.last_price, Some(price(2.0)))
+    }
+    m => panic!("unexpected message {:?}", m),
+  }
+  assert!(next(&mut sb).await.is_none());
+}
+
+#[tokio::test]
+async fn test_mock_update_credentials() {
+  let server = start().await;
+  let ticker = connect(&server, fast_policy(5)).await;
+  let handle = ticker.handle();
+  let mut sb = ticker.subscribe(&[408065], Some(Mode::Full)).await.unwrap();
+  connected(&mut sb).await;
+  sb.subscribe(&[256265], Some(Mode::LTP)).await.unwrap();
+  server.wait_for_mode(256265, Mode::LTP).await;
+
+  // the token is rejected, the current connection keeps streaming
+  let rejected = handle
+    .update_credentials(Credentials::new(API_KEY, "expired"))
+    .await;
+  assert!(matches!(
+    rejected,
+    Err(KiteTickerError::Handshake { status: 403, .. })
+  ));
+  assert_eq!(server.open_connections(), 1);
+
+  server.set_credentials(API_KEY, "next_day");
+  handle
+    .update_credentials(Credentials::new(API_KEY, "next_day"))
+    .await
+    .unwrap();
+  server.wait_for_mode(256265, Mode::LTP).await;
+  server.wait_for_mode(408065, Mode::Full).await;
+  connected(&mut sb).await;
+  match next(&mut sb).await {
+    Some(TickerMessage::Resubscribed { mut tokens }) => {
+      tokens.sort_unstable();
+      assert_eq!(tokens, vec![256265, 408065]);
+    }
+    m => panic!("unexpected message {:?}", m),
+  }
+  server.publish(&[tick(256265, 19000.0)]).unwrap();
+  match next(&mut sb).await {
+    Some(TickerMessage::Ticks(xs)) => {
+      assert_eq!(xs[0].instrument_token, 256265)
+    }
+    m => panic!("unexpected message {:?}", m),
+  }
+  assert_eq!(server.accepted_connections(), 2);
+}
+
+#[tokio::test]
+async fn test_mock_credentials_provider() {
+  let server = start().await;
+  let token = Arc::new(Mutex::new(ACCESS_TOKEN.to_string()));
+  let calls = Arc::new(AtomicUsize::new(0));
+  let ticker = KiteTickerAsync::builder()
+    .endpoint(server.url())
+    .credentials_provider({
+      let token = token.clone();
+      let calls = calls.clone();
+      move || {
+        calls.fetch_add(1, Ordering::SeqCst);
+        let token = token.lock().unwrap().clone();
+        async move { Ok(Credentials::new(API_KEY, token)) }
+      }
+    })
+    .reconnect_policy(fast_policy(5))
+    .connect()
+    .await
+    .unwrap();
+  let mut sb = ticker.subscribe(&[408065], Some(Mode::LTP)).await.unwrap();
+  connected(&mut sb).await;
+  server.wait_for_mode(408065, Mode::LTP).await;
+
+  // the token rotates while the connection is down
+  server.set_credentials(API_KEY, "next_day");
+  *token.lock().unwrap() = "next_day".to_string();
+  server.drop_connections();
+  loop {
+    match next(&mut sb).await {
+      Some(TickerMessage::Resubscribed { tokens }) => {
+        assert_eq!(tokens, vec![408065]);
+        break;
+      }
+      Some(
+        TickerMessage::Disconnected { .. }
+        | TickerMessage::Reconnecting { .. }
+        | TickerMessage::Connected { .. },
+      ) => continue,
+      m => panic!("unexpected message {:?}", m),
+    }
+  }
+  assert_eq!(calls.load(Ordering::SeqCst), 2);
+  server.wait_for_mode(408065, Mode::LTP).await;
+  server.publish(&[tick(408065, 1573.15)]).unwrap();
+  assert!(matches!(next(&mut sb).await, Some(TickerMessage::Ticks(_))));
+}
+
+#[tokio::test]
+async fn test_mock_metrics() {
+  use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+  let server = start().await;
+  let ticker = connect(&server, fast_policy(5)).await;
+  let metrics = ticker.handle().metrics();
+  let mut sb = ticker.subscribe(&[408065], Some(Mode::Full)).await.unwrap();
+  connected(&mut sb).await;
+  server.wait_for_mode(408065, Mode::Full).await;
+
+  let now = chrono::Utc::now().timestamp();
+  server
+    .publish(&[Tick {
+      exchange_timestamp: chrono::DateTime::from_timestamp(now - 1, 0)
+        .map(|at| at.fixed_offset()),
+      ..tick(408065, 1573.15)
+    }])
+    .unwrap();
+  assert!(matches!(next(&mut sb).await, Some(TickerMessage::Ticks(_))));
+  server.heartbeat();
+  assert!(matches!(
+    next(&mut sb).await,
+    Some(TickerMessage::Heartbeat)
+  ));
+  let good = encode_tick(&tick(408065, 1573.15), &Mode::LTP).unwrap();
+  server.send_binary(encode_frame(&[good, vec![0; 5]]));
+  assert!(matches!(next(&mut sb).await, Some(TickerMessage::Ticks(_))));
+  assert!(sb.next_message().await.is_err());
+
+  server.drop_connections();
+  loop {
+    if let Some(TickerMessage::Resubscribed { .. }) = next(&mut sb).await {
+      break;
+    }
+  }
+
+  let snapshot = metrics.snapshot();
+  assert_eq!(snapshot.frames, 3);
+  assert_eq!(snapshot.heartbeats, 1);
+  assert_eq!(snapshot.ticks, 2);
+  assert_eq!(snapshot.ticks_per_token[&408065], 2);
+  assert_eq!(snapshot.decode_errors, 1);
+  assert_eq!(snapshot.reconnects, 1);
+  assert!(snapshot.reconnect_attempts >= 1);
+  assert!(snapshot.bytes > 184);
+  assert_eq!(snapshot.latency.count, 1);
+  assert!(snapshot.latency.sum >= Duration::from_secs(1));
+
+  let endpoint = metrics.serve("127.0.0.1:0").await.unwrap();
+  let addr = endpoint.local_addr();
+  let get = move |path: &'static str| async move {
+    let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+    let request = format!("GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", path);
+    stream.write_all(request.as_bytes()).await.unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await.unwrap();
+    response
+  };
+  let response = get("/metrics").await;
+  assert!(response.starts_with("HTTP/1.1 200 OK"));
+  assert!(response.contains("\nkiteticker_ticks_total 2\n"));
+  assert!(response.contains("kiteticker_reconnects_total 1\n"));
+  assert!(get("/").await.starts_with("HTTP/1.1 404"));
+}
+
+#[derive(Clone, Default)]
+struct LogBuffer(Arc<Mutex<Vec<u8>>>);
+
+impl std::io::Write for LogBuffer {
+  fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+    self.0.lock().unwrap().extend_from_slice(buf);
+    Ok(buf.len())
+  }
+
+  fn flush(&mut self) -> std::io::Result<()> {
+    Ok(())
+  }
+}
+
+#[tokio::test]
+async fn test_mock_tracing() {
+  let logs = LogBuffer::default();
+  let subscriber = tracing_subscriber::fmt()
+    .with_max_level(tracing_subscriber::filter::LevelFilter::DEBUG)
+    .with_ansi(false)
+    .with_writer({
+      let logs = logs.clone();
+      move || logs.clone()
+    })
+    .finish();
+  let _guard = tracing::subscriber::set_default(subscriber);
+
+  let server = start().await;
+  let ticker = connect(&server, ReconnectPolicy::disabled()).await;
+  let mut sb = ticker.subscribe(&[408065], Some(Mode::Full)).await.unwrap();
+  connected(&mut sb).await;
+  server.wait_for_mode(408065, Mode::Full).await;
+
+  server.send_error("invalid token");
+  assert!(matches!(next(&mut sb).await, Some(TickerMessage::Error(_))));
+  server.send_binary(encode_frame(&[vec![0xde, 0xad, 0xbe, 0xef, 0, 0]]));
+  assert!(sb.next_message().await.is_err());
+  server.close_connections(1000, "bye");
+  while next(&mut sb).await.is_some() {}
+
+  let logs = String::from_utf8(logs.0.lock().unwrap().clone()).unwrap();
+  for expected in [
+    "connect{endpoint=ws://",
+    "connected",
+    "action=\"subscribe\" tokens=1",
+    "action=\"mode\" tokens=1 mode=Some(Full)",
+    "text message received",
+    "invalid token",
+    "excerpt=de ad be ef 00 00",
+    "code=1000 reason=bye",
+    "api_key: \"****_key\"",
+    "access_token: \"<redacted>\"",
+  ] {
+    assert!(logs.contains(expected), "missing {} in\n{}", expected, logs);
+  }
+  assert!(!logs.contains(&format!("access_token={}", ACCESS_TOKEN)));
+  assert!(!logs.contains(&format!("\"{}\"", ACCESS_TOKEN)));
+  assert!(!logs.contains(&format!("\"{}\"", API_KEY)));
+}
+
+#[tokio::test]
+async fn test_mock_raw_frames() {
+  let server = start().await;
+  let ticker = KiteTickerAsync::builder()
+    .endpoint(server.url())
+    .credentials(API_KEY, ACCESS_TOKEN)
+    .reconnect_policy(ReconnectPolicy::disabled())
+    .raw_frames(true)
+    .connect()
+    .await
+    .unwrap();
+  let metrics = ticker.handle().metrics();
+  let mut sb = ticker.subscribe(&[408065, 256265], None).await.unwrap();
+  connected(&mut sb).await;
+  server.wait_for_subscription(256265).await;
+
+  server
+    .publish(&[tick(408065, 1573.15), tick(256265, 19000.0)])
+    .unwrap();
+  match next(&mut sb).await {
+    Some(TickerMessage::Frame { bytes, received_at }) => {
+      assert!(received_at <= chrono::Utc::now());
+      let views = FrameView::new(&bytes)
+        .packets()
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+      assert_eq!(views.len(), 2);
+      assert_eq!(views[0].instrument_token(), 408065);
+      assert_eq!(views[0].last_price(), Some(price(1573.15)));
+      assert!(views[1].is_index());
+      assert_eq!(views[1].to_tick().unwrap().last_price, Some(price(19000.0)));
+    }
+    m => panic!("unexpected message {:?}", m),
+  }
+  server.heartbeat();
+  assert!(matches!(
+    next(&mut sb).await,
+    Some(TickerMessage::Heartbeat)
+  ));
+  assert_eq!(metrics.snapshot().ticks, 2);
+  sb.close().await.unwrap();
+}
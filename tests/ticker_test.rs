@@ -1,3 +1,11 @@
+// the live tests predate clippy's current lints, and are kept as written
+#![allow(
+  clippy::assertions_on_constants,
+  clippy::bool_assert_comparison,
+  clippy::len_zero,
+  clippy::unused_unit
+)]
+
 mod common;
 
 use kiteticker_async::ticker::*;
@@ -110,6 +118,12 @@ async fn test_unsubscribe() {
             break;
           }
         }
+        Some(TickerMessage::Heartbeat) => {
+          if loop_cnt > 4 {
+            break;
+          }
+          loop_cnt += 1;
+        }
         _ => {
           continue;
         }
@@ -213,6 +227,12 @@ async fn test_removal_from_subscribed() {
             break;
           }
         }
+        Some(TickerMessage::Heartbeat) => {
+          if loop_cnt > 4 {
+            break;
+          }
+          loop_cnt += 1;
+        }
         _ => {
           continue;
         }